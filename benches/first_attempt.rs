@@ -0,0 +1,61 @@
+//! the happy path where the first attempt succeeds
+//! `repeatedly_try` wraps every request in some services and almost always succeeds right away
+//!     so this should cost little more than calling the closure itself
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use retryable_result::{repeatedly_try, repeatedly_try_blocking, Retryable, RetryableResult};
+use std::time::{Duration, Instant};
+
+struct NeverHappens;
+
+impl Retryable for NeverHappens {
+    type FatalError = ();
+
+    fn to_fatal(self) -> Self::FatalError {}
+
+    fn wait_time(
+        &self,
+        _my_time: Instant,
+        _previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        None
+    }
+}
+
+fn no_fatal_logger(_: &(), _: Instant, _: &mut ()) {}
+
+fn no_recoverable_logger(_: &NeverHappens, _: Instant, _: &mut ()) {}
+
+async fn succeeds(x: u64) -> RetryableResult<u64, NeverHappens, ()> {
+    RetryableResult::GoodResult(black_box(x) + 1)
+}
+
+fn first_attempt_success(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("a current thread runtime builds");
+    c.bench_function("closure alone", |b| {
+        b.iter(|| runtime.block_on(succeeds(black_box(1))));
+    });
+    c.bench_function("repeatedly_try first attempt", |b| {
+        b.iter(|| {
+            runtime.block_on(repeatedly_try(
+                succeeds,
+                black_box(1),
+                (&mut (), Some(no_fatal_logger), Some(no_recoverable_logger)),
+            ))
+        });
+    });
+    c.bench_function("repeatedly_try_blocking first attempt", |b| {
+        b.iter(|| {
+            repeatedly_try_blocking(
+                |x: u64| RetryableResult::<_, NeverHappens, ()>::GoodResult(black_box(x) + 1),
+                black_box(1),
+                (&mut (), Some(no_fatal_logger), Some(no_recoverable_logger)),
+            )
+        });
+    });
+}
+
+criterion_group!(benches, first_attempt_success);
+criterion_main!(benches);
@@ -0,0 +1,141 @@
+//! the retry loop driven by a script of outcomes and wait decisions from the fuzzer
+//! every byte after the first is one attempt
+//!     `byte % 3` picks success, a recoverable error or a fatal error
+//!     and a recoverable error's `byte % 4` picks give up, retry immediately or wait `byte` ms
+//!     once the script runs out every attempt is a recoverable error that retries immediately
+//!     so that only the attempt cap can end the loop
+//! the first byte, when it is odd, turns on `max_wait`
+//! the waiting goes to a sleeper that only writes down how long it was asked to wait
+//!     so the whole loop finishes in a single poll and the fuzzer never actually sleeps
+//! the invariants checked afterwards
+//!     the loop ends, and within `MAX_ATTEMPTS` attempts
+//!     it is a success exactly when the last attempt was
+//!     no wait is longer than what was scripted or than `max_wait`
+//!     there is at most one wait between each pair of attempts
+//!     the loggers have been given every error by the time it gives up
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use retryable_result::{RetryBuilder, Retryable, RetryableResult, Sleeper, WaitDecision};
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+const MAX_ATTEMPTS: usize = 16;
+
+#[derive(Debug)]
+struct Scripted(u8);
+
+impl Retryable for Scripted {
+    type FatalError = u8;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.0
+    }
+
+    fn wait_time(
+        &self,
+        _my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        if previous_retriable_failures.len() + 1 >= MAX_ATTEMPTS || self.0 % 4 == 0 {
+            return None;
+        }
+        Some(Duration::from_millis(u64::from(self.0)))
+    }
+
+    fn wait_decision(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> WaitDecision {
+        let wait = self.wait_time(my_time, previous_retriable_failures);
+        if self.0 % 4 == 1 {
+            WaitDecision::immediate_unless_give_up(wait)
+        } else {
+            WaitDecision::from(wait)
+        }
+    }
+}
+
+struct RecordingSleeper<'a>(&'a RefCell<Vec<Duration>>);
+
+impl Sleeper for RecordingSleeper<'_> {
+    async fn sleep(&self, how_long: Duration) {
+        self.0.borrow_mut().push(how_long);
+    }
+}
+
+#[derive(Default)]
+struct Logged {
+    recoverable: usize,
+    fatal: usize,
+}
+
+fn outcome_of(step: Option<u8>) -> RetryableResult<u8, Scripted, u8> {
+    match step {
+        Some(byte) if byte % 3 == 0 => RetryableResult::GoodResult(byte),
+        Some(byte) if byte % 3 == 1 => RetryableResult::Retryable(Scripted(byte)),
+        Some(byte) => RetryableResult::Fatal(byte),
+        None => RetryableResult::Retryable(Scripted(1)),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&config, script)) = data.split_first() else {
+        return;
+    };
+    let max_wait = (config % 2 == 1).then(|| Duration::from_millis(u64::from(config)));
+    let sleeps = RefCell::new(Vec::new());
+    let attempts = Cell::new(0);
+    let last_outcome = Cell::new(None);
+
+    let mut builder = RetryBuilder::new().sleeper(RecordingSleeper(&sleeps));
+    if let Some(max_wait) = max_wait {
+        builder = builder.max_wait(max_wait);
+    }
+    let mut logged = Logged::default();
+    let retry_loop = builder.run(
+        |()| {
+            let step = script.get(attempts.get()).copied();
+            attempts.set(attempts.get() + 1);
+            let outcome = outcome_of(step);
+            last_outcome.set(Some(matches!(outcome, RetryableResult::GoodResult(_))));
+            async move { outcome }
+        },
+        (),
+        (
+            &mut logged,
+            Some(|_: &u8, _: Instant, logged: &mut Logged| logged.fatal += 1),
+            Some(|_: &Scripted, _: Instant, logged: &mut Logged| logged.recoverable += 1),
+        ),
+    );
+    let mut cx = Context::from_waker(Waker::noop());
+    let Poll::Ready(outcome) = pin!(retry_loop).poll(&mut cx) else {
+        panic!("nothing in the loop should ever be pending");
+    };
+
+    let attempts = attempts.get();
+    assert!((1..=MAX_ATTEMPTS).contains(&attempts));
+    assert_eq!(outcome.is_ok(), last_outcome.get() == Some(true));
+    let sleeps = sleeps.into_inner();
+    assert!(sleeps.len() < attempts);
+    for slept in &sleeps {
+        assert!(*slept <= Duration::from_millis(u64::from(u8::MAX)));
+        if let Some(max_wait) = max_wait {
+            assert!(*slept <= max_wait);
+        }
+    }
+    if outcome.is_err() {
+        assert_eq!(logged.fatal, 1);
+        assert_eq!(logged.recoverable, attempts - 1);
+    } else {
+        assert_eq!(logged.fatal, 0);
+        assert_eq!(logged.recoverable, 0);
+    }
+});
@@ -0,0 +1,1311 @@
+//! ready made policies for how long to wait between attempts
+//! an implementation of `Retryable` can delegate its `wait_time` to one of these
+//!     instead of writing the duration arithmetic by hand
+//! all of the arithmetic here saturates at the configured maximum wait
+//!     so a long run of recoverable errors can never cause a panic from `Duration` overflow
+
+use crate::retryable::{Retryable, RetryableResult};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+pub trait Backoff {
+    //! the same shape as `Retryable::wait_time` but as a standalone policy
+    //! it is generic over what type of recoverable error is in the history
+    //!     so one policy can be shared by many error types
+    //! returning None means give up
+    fn next_delay<R>(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>;
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        //! `next_delay` for the recoverable error `error`, scaled by its `severity`
+        //!     for use from inside `wait_time`, as `POLICY.next_delay_for(self, my_time, previous)`
+        //! whether to give up is still only up to `next_delay`
+        self.next_delay(my_time, previous_retriable_failures)
+            .map(|wait| scaled_by_severity(wait, error.severity()))
+    }
+}
+
+#[must_use]
+pub fn scaled_by_severity(wait: Duration, severity: f64) -> Duration {
+    //! `wait` times `severity`, saturating at `Duration::MAX`
+    //! a severity of 0 or less is no wait at all
+    //!     and one that is not a number leaves `wait` as it was
+    if severity.is_nan() {
+        return wait;
+    }
+    if severity <= 0.0 {
+        return Duration::ZERO;
+    }
+    Duration::try_from_secs_f64(wait.as_secs_f64() * severity).unwrap_or(Duration::MAX)
+}
+
+/// wait `base`, then `base * multiplier`, then `base * multiplier^2` and so on
+/// never waiting more than `max_wait` at a time
+/// and giving up once `max_attempts` attempts have all failed recoverably
+/// it only counts the previous failures and never measures the gaps between them
+///     so failures clustered at the same instant still back off from `base`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    pub base: Duration,
+    pub multiplier: u32,
+    pub max_wait: Duration,
+    pub max_attempts: usize,
+}
+
+/// the parameters of the crate's backoff curve, for driving a loop by hand with `next_backoff`
+pub type RetryConfig = ExponentialBackoff;
+
+impl ExponentialBackoff {
+    /// 100ms, 200ms, 400ms and 800ms, giving up on the 5th recoverable failure
+    ///     with waits capped at 30s for anyone who raises `max_attempts`
+    /// a reasonable start for a call to another service when there is nothing better to go on
+    pub const DEFAULT: Self = Self {
+        base: Duration::from_millis(100),
+        multiplier: 2,
+        max_wait: Duration::from_secs(30),
+        max_attempts: 5,
+    };
+
+    #[must_use]
+    pub fn delay_for_attempt(&self, previous_failures: usize) -> Option<Duration> {
+        //! how long to wait after a recoverable failure
+        //!     when there were already `previous_failures` recoverable failures before it
+        //! None once that failure used up the last of the `max_attempts`
+        next_backoff(self, previous_failures)
+    }
+}
+
+#[must_use]
+pub fn next_backoff(config: &RetryConfig, attempt: usize) -> Option<Duration> {
+    //! the backoff curve on its own, with no `Retryable` and no history
+    //! `attempt` is how many recoverable failures came before the one that was just seen
+    //!     so it is 0 right after the first failure
+    //! None once that failure used up the last of the `max_attempts`
+    //! this is a pure function, it does not look at the clock and has no randomness
+    if attempt.saturating_add(1) >= config.max_attempts {
+        return None;
+    }
+    let delay = u32::try_from(attempt)
+        .ok()
+        .and_then(|exponent| config.multiplier.checked_pow(exponent))
+        .and_then(|factor| config.base.checked_mul(factor))
+        .unwrap_or(config.max_wait);
+    Some(delay.min(config.max_wait))
+}
+
+pub fn next_backoff_with_jitter<J>(
+    config: &RetryConfig,
+    attempt: usize,
+    jitter: J,
+) -> Option<Duration>
+where
+    J: FnOnce(Duration) -> Duration,
+{
+    //! `next_backoff` with the delay passed through `jitter`
+    //!     which is where any randomness gets injected
+    //! the jittered delay is still capped at `max_wait`
+    next_backoff(config, attempt).map(|delay| jitter(delay).min(config.max_wait))
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        //! `ExponentialBackoff::DEFAULT`
+        Self::DEFAULT
+    }
+}
+
+impl Backoff for ExponentialBackoff {
+    fn next_delay<R>(
+        &self,
+        _my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration> {
+        self.delay_for_attempt(previous_retriable_failures.len())
+    }
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        _my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        //! still never more than `max_wait`, however severe
+        self.delay_for_attempt(previous_retriable_failures.len())
+            .map(|wait| scaled_by_severity(wait, error.severity()).min(self.max_wait))
+    }
+}
+
+/// wait `base`, then `base * multiplier`, then `base * multiplier^2` and so on
+/// but give up instead of waiting when that wait would end after `total_budget` has passed
+/// the elapsed time is measured from the first recoverable failure in the history
+///     because that is the earliest instant the history knows about
+///     so the running time of the very first attempt is not part of the budget
+/// a wait that ends exactly at the edge of the budget is still allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetedExponential {
+    pub base: Duration,
+    pub multiplier: u32,
+    pub total_budget: Duration,
+}
+
+impl BudgetedExponential {
+    /// 100ms, 200ms, 400ms and so on for as long as the waits fit into 30s
+    ///     which is 8 retries when the attempts themselves fail instantly
+    pub const DEFAULT: Self = Self {
+        base: Duration::from_millis(100),
+        multiplier: 2,
+        total_budget: Duration::from_secs(30),
+    };
+}
+
+impl BudgetedExponential {
+    fn elapsed<R>(my_time: Instant, previous_retriable_failures: &[(R, Instant)]) -> Duration {
+        previous_retriable_failures
+            .first()
+            .map_or(Duration::ZERO, |(_, first)| {
+                my_time.saturating_duration_since(*first)
+            })
+    }
+}
+
+impl Default for BudgetedExponential {
+    fn default() -> Self {
+        //! `BudgetedExponential::DEFAULT`
+        Self::DEFAULT
+    }
+}
+
+impl Backoff for BudgetedExponential {
+    fn next_delay<R>(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration> {
+        let elapsed = Self::elapsed(my_time, previous_retriable_failures);
+        let proposed = u32::try_from(previous_retriable_failures.len())
+            .ok()
+            .and_then(|exponent| self.multiplier.checked_pow(exponent))
+            .and_then(|factor| self.base.checked_mul(factor))?;
+        let ends_at = elapsed.checked_add(proposed)?;
+        (ends_at <= self.total_budget).then_some(proposed)
+    }
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        //! gives up when the wait scaled by `severity` would no longer fit into `total_budget`
+        let wait = scaled_by_severity(
+            self.next_delay(my_time, previous_retriable_failures)?,
+            error.severity(),
+        );
+        let ends_at = Self::elapsed(my_time, previous_retriable_failures).checked_add(wait)?;
+        (ends_at <= self.total_budget).then_some(wait)
+    }
+}
+
+/// any policy with a hard ceiling on attempts on top
+///     giving up once `max_attempts` attempts have all failed recoverably
+///     the same way `ExponentialBackoff` counts them
+/// otherwise the wait is whatever `inner` says, including when `inner` gives up sooner
+/// so that the ceiling travels with the policy instead of being a knob on each call site
+///     such as a `BudgetedExponential` that should also never make more than 5 attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxAttempts<B>
+where
+    B: Backoff,
+{
+    pub inner: B,
+    pub max_attempts: usize,
+}
+
+impl<B> Backoff for MaxAttempts<B>
+where
+    B: Backoff,
+{
+    fn next_delay<R>(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration> {
+        if previous_retriable_failures.len().saturating_add(1) >= self.max_attempts {
+            return None;
+        }
+        self.inner.next_delay(my_time, previous_retriable_failures)
+    }
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        //! `inner` scales by `severity` itself, so its own limits still apply
+        if previous_retriable_failures.len().saturating_add(1) >= self.max_attempts {
+            return None;
+        }
+        self.inner
+            .next_delay_for(error, my_time, previous_retriable_failures)
+    }
+}
+
+/// the outcomes of the last `capacity` attempts, across every call that shares it
+///     so a policy can look at how often things have been failing lately
+///     and not only at the failures of the one call it is deciding for
+/// nothing is recorded automatically, the attempts report themselves with `observe`
+///     or with `record_success` and `record_failure`
+#[derive(Debug)]
+pub struct FailureWindow {
+    capacity: usize,
+    failed: Mutex<VecDeque<bool>>,
+}
+
+impl FailureWindow {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        //! remembers at most `capacity` outcomes, forgetting the oldest one to make room
+        Self {
+            capacity,
+            failed: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut window = self
+            .failed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if window.len() == self.capacity {
+            window.pop_front();
+        }
+        window.push_back(failed);
+    }
+
+    pub fn record_success(&self) {
+        self.record(false);
+    }
+
+    pub fn record_failure(&self) {
+        self.record(true);
+    }
+
+    pub fn observe<T, R, F>(&self, outcome: &RetryableResult<T, R, F>)
+    where
+        R: Retryable<FatalError = F>,
+    {
+        //! a `GoodResult` is a success and a `Retryable` is a failure
+        //! a `Fatal` is not recorded at all
+        //!     since it is usually the request that was bad rather than the dependency being overloaded
+        match outcome {
+            RetryableResult::GoodResult(_) => self.record_success(),
+            RetryableResult::Retryable(_) => self.record_failure(),
+            RetryableResult::Fatal(_) => {}
+        }
+    }
+
+    #[must_use]
+    pub fn failures(&self) -> (usize, usize) {
+        //! how many of the remembered outcomes were failures, and how many are remembered
+        let window = self
+            .failed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        (
+            window.iter().filter(|failed| **failed).count(),
+            window.len(),
+        )
+    }
+}
+
+/// wait somewhere between `base` and `max_wait` depending on the recent failure rate
+///     `base` when nothing in the window failed and `max_wait` when everything did
+///     and in proportion for everything in between, so 8 failures out of 10 waits 80% of the way
+/// with an empty window it waits `base`
+/// gives up once `max_attempts` attempts of this call have all failed recoverably
+///     the same way `ExponentialBackoff` counts them
+/// the `window` is shared through an `Arc`, so clones of this policy all see the same rate
+#[derive(Debug, Clone)]
+pub struct RateBasedBackoff {
+    pub window: Arc<FailureWindow>,
+    pub base: Duration,
+    pub max_wait: Duration,
+    pub max_attempts: usize,
+}
+
+impl RateBasedBackoff {
+    #[must_use]
+    pub fn delay_for_rate(&self, failures: usize, outcomes: usize) -> Duration {
+        //! the wait for `failures` out of `outcomes`, without looking at the window
+        let spread = self.max_wait.saturating_sub(self.base);
+        let (Ok(failures), Ok(outcomes)) = (u32::try_from(failures), u32::try_from(outcomes))
+        else {
+            return self.max_wait;
+        };
+        if outcomes == 0 {
+            return self.base.min(self.max_wait);
+        }
+        let extra = spread
+            .checked_mul(failures.min(outcomes))
+            .map_or(spread, |scaled| scaled / outcomes);
+        self.base.saturating_add(extra).min(self.max_wait)
+    }
+}
+
+impl Backoff for RateBasedBackoff {
+    fn next_delay<R>(
+        &self,
+        _my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration> {
+        if previous_retriable_failures.len().saturating_add(1) >= self.max_attempts {
+            return None;
+        }
+        let (failures, outcomes) = self.window.failures();
+        Some(self.delay_for_rate(failures, outcomes))
+    }
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        //! still never more than `max_wait`, however severe
+        self.next_delay(my_time, previous_retriable_failures)
+            .map(|wait| scaled_by_severity(wait, error.severity()).min(self.max_wait))
+    }
+}
+
+/// a policy whose parameters can be changed while it is in use
+///     such as making retries less aggressive during an incident without a restart
+/// every decision reads whatever policy was stored last
+///     so a loop that is already running picks up the change at its next recoverable error
+/// clones share the same policy, so keep one clone wherever the config gets reloaded
+#[derive(Debug, Clone, Default)]
+pub struct HotReload<B>
+where
+    B: Backoff,
+{
+    current: Arc<RwLock<B>>,
+}
+
+impl<B> HotReload<B>
+where
+    B: Backoff,
+{
+    #[must_use]
+    pub fn new(initial: B) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(initial)),
+        }
+    }
+
+    pub fn store(&self, policy: B) {
+        //! every decision from now on, by this and by all of its clones, uses `policy`
+        *self
+            .current
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = policy;
+    }
+
+    #[must_use]
+    pub fn load(&self) -> B
+    where
+        B: Clone,
+    {
+        //! the policy that is in use right now
+        self.current
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl<B> Backoff for HotReload<B>
+where
+    B: Backoff,
+{
+    fn next_delay<R>(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration> {
+        self.current
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .next_delay(my_time, previous_retriable_failures)
+    }
+
+    fn next_delay_for<R>(
+        &self,
+        error: &R,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> Option<Duration>
+    where
+        R: Retryable,
+    {
+        self.current
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .next_delay_for(error, my_time, previous_retriable_failures)
+    }
+}
+
+/// a recoverable error whose whole schedule is fixed at compile time
+///     `ConstRetry<R, 5, 100>` retries 5 times, waiting 100ms, 200ms, 400ms, 800ms and 1600ms
+/// the fatal error is whatever `R` converts to, the `wait_time` of `R` is not used
+///     but its `severity` still scales every wait
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstRetry<R, const MAX_RETRIES: usize, const BASE_MS: u64>(pub R)
+where
+    R: Retryable;
+
+impl<R, const MAX_RETRIES: usize, const BASE_MS: u64> ConstRetry<R, MAX_RETRIES, BASE_MS>
+where
+    R: Retryable,
+{
+    /// the schedule as an `ExponentialBackoff`
+    pub const POLICY: ExponentialBackoff = ExponentialBackoff {
+        base: Duration::from_millis(BASE_MS),
+        multiplier: 2,
+        max_wait: Duration::MAX,
+        max_attempts: MAX_RETRIES.saturating_add(1),
+    };
+}
+
+impl<R, const MAX_RETRIES: usize, const BASE_MS: u64> Retryable
+    for ConstRetry<R, MAX_RETRIES, BASE_MS>
+where
+    R: Retryable,
+{
+    type FatalError = R::FatalError;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.0.to_fatal()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        Self::POLICY.next_delay_for(self, my_time, previous_retriable_failures)
+    }
+
+    fn severity(&self) -> f64 {
+        self.0.severity()
+    }
+}
+
+pub trait RateLimitReset: Retryable {
+    //! for recoverable errors that can say exactly when it is okay to try again
+    //!     such as a `429` with a header giving when the rate limit window resets
+    fn reset_at(&self) -> Option<Instant>;
+}
+
+/// a recoverable error that waits exactly until its `reset_at` instead of doing any backoff math
+/// when there is no `reset_at` the `fallback` policy decides how long to wait
+///     scaled by the `severity` of `error`, while a `reset_at` is always waited for exactly
+/// the `fallback` policy also decides when to give up, even when there is a `reset_at`
+///     so that a stream of rate limited errors can not keep going forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited<R, B = ExponentialBackoff>
+where
+    R: RateLimitReset,
+    B: Backoff,
+{
+    pub error: R,
+    pub fallback: B,
+}
+
+impl<R, B> Retryable for RateLimited<R, B>
+where
+    R: RateLimitReset,
+    B: Backoff,
+{
+    type FatalError = R::FatalError;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.error.to_fatal()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        let fallback_wait =
+            self.fallback
+                .next_delay_for(self, my_time, previous_retriable_failures)?;
+        // a reset that is not in the future says nothing about how long to wait
+        //     so that goes to the fallback as well instead of retrying with no wait at all
+        Some(
+            self.error
+                .reset_at()
+                .and_then(|reset| reset.checked_duration_since(my_time))
+                .filter(|until_reset| !until_reset.is_zero())
+                .unwrap_or(fallback_wait),
+        )
+    }
+
+    fn severity(&self) -> f64 {
+        self.error.severity()
+    }
+}
+
+/// a recoverable error that gives up once the same problem has come up `repeats` times in a row
+///     on the theory that waiting longer will not fix an error that keeps coming back unchanged
+/// which errors count as the same problem is up to `same`
+///     such as comparing only the kind of error, so that every 5xx is one problem
+/// until then the `fallback` policy decides how long to wait and when to give up anyway
+#[derive(Debug, Clone, Copy)]
+pub struct GiveUpOnRepeat<R, B = ExponentialBackoff, Same = fn(&R, &R) -> bool>
+where
+    R: Retryable,
+    B: Backoff,
+    Same: Fn(&R, &R) -> bool,
+{
+    pub error: R,
+    pub fallback: B,
+    pub repeats: usize,
+    pub same: Same,
+}
+
+impl<R, B> GiveUpOnRepeat<R, B>
+where
+    R: Retryable + PartialEq,
+    B: Backoff,
+{
+    #[must_use]
+    pub fn new(error: R, fallback: B, repeats: usize) -> Self {
+        //! errors that are equal count as the same problem
+        Self {
+            error,
+            fallback,
+            repeats,
+            same: R::eq,
+        }
+    }
+}
+
+impl<R, B, Same> GiveUpOnRepeat<R, B, Same>
+where
+    R: Retryable,
+    B: Backoff,
+    Same: Fn(&R, &R) -> bool,
+{
+    #[must_use]
+    pub fn with_same(error: R, fallback: B, repeats: usize, same: Same) -> Self {
+        //! errors for which `same` is true count as the same problem
+        //!     it is given the earlier error first
+        Self {
+            error,
+            fallback,
+            repeats,
+            same,
+        }
+    }
+
+    #[must_use]
+    pub fn in_a_row(&self, previous_retriable_failures: &[(Self, Instant)]) -> usize {
+        //! how many times this problem has come up in a row, counting this time
+        1 + previous_retriable_failures
+            .iter()
+            .rev()
+            .take_while(|(earlier, _)| (self.same)(&earlier.error, &self.error))
+            .count()
+    }
+}
+
+impl<R, B, Same> Retryable for GiveUpOnRepeat<R, B, Same>
+where
+    R: Retryable,
+    B: Backoff,
+    Same: Fn(&R, &R) -> bool,
+{
+    type FatalError = R::FatalError;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.error.to_fatal()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        if self.in_a_row(previous_retriable_failures) >= self.repeats {
+            return None;
+        }
+        self.fallback
+            .next_delay_for(self, my_time, previous_retriable_failures)
+    }
+
+    fn severity(&self) -> f64 {
+        self.error.severity()
+    }
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    struct TooManyRequests(Option<std::time::Instant>);
+
+    impl Retryable for TooManyRequests {
+        type FatalError = u16;
+
+        fn to_fatal(self) -> Self::FatalError {
+            429
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    impl super::RateLimitReset for TooManyRequests {
+        fn reset_at(&self) -> Option<std::time::Instant> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn exponential_schedule() {
+        use super::ExponentialBackoff;
+        use std::time::Duration;
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            max_wait: Duration::from_millis(500),
+            max_attempts: 6,
+        };
+        let schedule: Vec<_> = (0..6).map(|n| policy.delay_for_attempt(n)).collect();
+        assert_eq!(
+            schedule,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_millis(500)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn default_policies() {
+        use super::{Backoff, BudgetedExponential, ExponentialBackoff};
+        use std::time::{Duration, Instant};
+        let policy = ExponentialBackoff::default();
+        assert_eq!(policy, ExponentialBackoff::DEFAULT);
+        let schedule: Vec<_> = (0..5).map(|n| policy.delay_for_attempt(n)).collect();
+        assert_eq!(
+            schedule,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(800)),
+                None,
+            ]
+        );
+
+        let budgeted = BudgetedExponential::default();
+        assert_eq!(budgeted, BudgetedExponential::DEFAULT);
+        let mut now = Instant::now();
+        let mut history = Vec::new();
+        while let Some(wait) = budgeted.next_delay(now, &history) {
+            history.push(((), now));
+            now += wait;
+        }
+        assert_eq!(history.len(), 8);
+    }
+
+    #[test]
+    fn exponential_saturates_instead_of_overflowing() {
+        use super::{Backoff, ExponentialBackoff};
+        use std::time::{Duration, Instant};
+        let policy = ExponentialBackoff {
+            base: Duration::from_secs(u64::MAX / 2),
+            multiplier: u32::MAX,
+            max_wait: Duration::from_secs(60),
+            max_attempts: usize::MAX,
+        };
+        for previous_failures in [0, 1, 2, 40, 1000, 1 << 40] {
+            assert_eq!(
+                policy.delay_for_attempt(previous_failures),
+                Some(Duration::from_secs(60))
+            );
+        }
+        let now = Instant::now();
+        let history = vec![((), now); 100];
+        assert_eq!(
+            policy.next_delay(now, &history),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(policy.delay_for_attempt(usize::MAX - 1), None);
+    }
+
+    #[test]
+    fn rate_limited_waits_until_reset() {
+        use super::{ExponentialBackoff, RateLimited};
+        use std::time::{Duration, Instant};
+        let fallback = ExponentialBackoff {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            max_wait: Duration::from_secs(10),
+            max_attempts: 3,
+        };
+        let now = Instant::now();
+        let reset = now + Duration::from_millis(1234);
+        let with_reset = |reset| RateLimited {
+            error: TooManyRequests(reset),
+            fallback: ExponentialBackoff { ..fallback },
+        };
+        assert_eq!(
+            with_reset(Some(reset)).wait_time(now, &[]),
+            Some(Duration::from_millis(1234))
+        );
+        assert_eq!(
+            with_reset(Some(now)).wait_time(now + Duration::from_millis(5), &[]),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            with_reset(None).wait_time(now, &[(with_reset(None), now)]),
+            Some(Duration::from_millis(200))
+        );
+        let exhausted = [(with_reset(None), now), (with_reset(None), now)];
+        assert_eq!(with_reset(Some(reset)).wait_time(now, &exhausted), None);
+        assert_eq!(with_reset(Some(reset)).to_fatal(), 429);
+    }
+
+    #[test]
+    fn clustered_failures_still_back_off() {
+        use super::{Backoff, ExponentialBackoff, RateLimited};
+        use std::time::{Duration, Instant};
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            max_wait: Duration::from_secs(10),
+            max_attempts: 10,
+        };
+        let now = Instant::now();
+        let mut history = Vec::new();
+        for expected_ms in [100, 200, 400, 800] {
+            assert_eq!(
+                policy.next_delay(now, &history),
+                Some(Duration::from_millis(expected_ms))
+            );
+            history.push(((), now));
+        }
+        let stale = RateLimited {
+            error: TooManyRequests(Some(now)),
+            fallback: ExponentialBackoff { ..policy },
+        };
+        assert_eq!(stale.wait_time(now, &[]), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn standalone_next_backoff() {
+        use super::{next_backoff, next_backoff_with_jitter, RetryConfig};
+        use std::time::Duration;
+        let config = RetryConfig {
+            base: Duration::from_millis(10),
+            multiplier: 3,
+            max_wait: Duration::from_millis(100),
+            max_attempts: 4,
+        };
+        assert_eq!(next_backoff(&config, 0), Some(Duration::from_millis(10)));
+        assert_eq!(next_backoff(&config, 1), Some(Duration::from_millis(30)));
+        assert_eq!(next_backoff(&config, 2), Some(Duration::from_millis(90)));
+        assert_eq!(next_backoff(&config, 3), None);
+        assert_eq!(
+            next_backoff_with_jitter(&config, 1, |d| d / 2),
+            Some(Duration::from_millis(15))
+        );
+        assert_eq!(
+            next_backoff_with_jitter(&config, 2, |d| d * 2),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(next_backoff_with_jitter(&config, 3, |d| d), None);
+    }
+
+    #[test]
+    fn const_schedule() {
+        use super::ConstRetry;
+        use crate::retryable::Retryable;
+        use std::time::{Duration, Instant};
+        type FiveFrom100 = ConstRetry<TooManyRequests, 5, 100>;
+        let now = Instant::now();
+        let mut history: Vec<(FiveFrom100, Instant)> = Vec::new();
+        let mut waits = Vec::new();
+        while let Some(wait) = ConstRetry(TooManyRequests(None)).wait_time(now, &history) {
+            waits.push(wait);
+            history.push((ConstRetry(TooManyRequests(None)), now));
+        }
+        assert_eq!(
+            waits,
+            [100, 200, 400, 800, 1600]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+        let last: FiveFrom100 = ConstRetry(TooManyRequests(None));
+        assert_eq!(last.to_fatal(), 429);
+    }
+
+    #[test]
+    fn attempt_ceiling_on_any_policy() {
+        use super::{Backoff, BudgetedExponential, ExponentialBackoff, MaxAttempts};
+        use std::time::{Duration, Instant};
+        let now = Instant::now();
+        let capped = MaxAttempts {
+            inner: BudgetedExponential {
+                base: Duration::from_millis(1),
+                multiplier: 1,
+                total_budget: Duration::from_secs(3600),
+            },
+            max_attempts: 3,
+        };
+        assert_eq!(
+            capped.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            capped.next_delay(now, &[((), now)]),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(capped.next_delay(now, &[((), now), ((), now)]), None);
+
+        let inner_gives_up_first = MaxAttempts {
+            inner: ExponentialBackoff {
+                max_attempts: 2,
+                ..ExponentialBackoff::DEFAULT
+            },
+            max_attempts: 10,
+        };
+        assert_eq!(inner_gives_up_first.next_delay(now, &[((), now)]), None);
+
+        let nested = MaxAttempts {
+            inner: capped,
+            max_attempts: 2,
+        };
+        assert_eq!(nested.next_delay(now, &[((), now)]), None);
+    }
+
+    #[test]
+    fn rate_based_waits_follow_the_window() {
+        use super::{Backoff, FailureWindow, RateBasedBackoff};
+        use crate::retryable::RetryableResult;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        let now = Instant::now();
+        let policy = RateBasedBackoff {
+            window: Arc::new(FailureWindow::new(10)),
+            base: Duration::from_millis(100),
+            max_wait: Duration::from_millis(1100),
+            max_attempts: 4,
+        };
+        let shared = policy.clone();
+        assert_eq!(
+            policy.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(100))
+        );
+
+        for _ in 0..10 {
+            shared.window.record_success();
+        }
+        assert_eq!(policy.window.failures(), (0, 10));
+        assert_eq!(
+            policy.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(100))
+        );
+
+        for _ in 0..8 {
+            shared.window.record_failure();
+        }
+        assert_eq!(policy.window.failures(), (8, 10));
+        assert_eq!(
+            policy.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(900))
+        );
+
+        for i in 0..10 {
+            let outcome = if i % 2 == 0 {
+                RetryableResult::<(), TooManyRequests, u16>::Retryable(TooManyRequests(None))
+            } else {
+                RetryableResult::GoodResult(())
+            };
+            shared.window.observe(&outcome);
+        }
+        shared
+            .window
+            .observe(&RetryableResult::<(), TooManyRequests, u16>::Fatal(400));
+        assert_eq!(policy.window.failures(), (5, 10));
+        assert_eq!(
+            policy.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(600))
+        );
+
+        for _ in 0..10 {
+            shared.window.record_failure();
+        }
+        assert_eq!(
+            policy.next_delay::<()>(now, &[]),
+            Some(Duration::from_millis(1100))
+        );
+        assert_eq!(policy.next_delay(now, &[((), now); 3]), None);
+    }
+
+    #[test]
+    fn rate_based_never_overflows() {
+        use super::{FailureWindow, RateBasedBackoff};
+        use std::sync::Arc;
+        use std::time::Duration;
+        let policy = RateBasedBackoff {
+            window: Arc::new(FailureWindow::new(0)),
+            base: Duration::from_secs(1),
+            max_wait: Duration::MAX,
+            max_attempts: 2,
+        };
+        policy.window.record_failure();
+        assert_eq!(policy.window.failures(), (0, 0));
+        assert_eq!(policy.delay_for_rate(0, 0), Duration::from_secs(1));
+        assert_eq!(policy.delay_for_rate(3, 4), Duration::MAX);
+        assert_eq!(policy.delay_for_rate(usize::MAX, usize::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn budget_edges() {
+        use super::{Backoff, BudgetedExponential};
+        use std::time::{Duration, Instant};
+        let policy = BudgetedExponential {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            total_budget: Duration::from_millis(1000),
+        };
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(policy.next_delay::<()>(start, &[]), Some(ms(100)));
+        let history = [((), start)];
+        assert_eq!(policy.next_delay(start + ms(100), &history), Some(ms(200)));
+        assert_eq!(policy.next_delay(start + ms(800), &history), Some(ms(200)));
+        assert_eq!(policy.next_delay(start + ms(801), &history), None);
+        let history = [((), start), ((), start + ms(300))];
+        assert_eq!(policy.next_delay(start + ms(600), &history), Some(ms(400)));
+        assert_eq!(policy.next_delay(start + ms(601), &history), None);
+        let history = [((), start), ((), start), ((), start), ((), start)];
+        assert_eq!(policy.next_delay(start, &history), None);
+
+        let exact = BudgetedExponential {
+            total_budget: ms(100),
+            ..policy
+        };
+        assert_eq!(exact.next_delay::<()>(start, &[]), Some(ms(100)));
+        let none_at_all = BudgetedExponential {
+            total_budget: Duration::ZERO,
+            ..exact
+        };
+        assert_eq!(none_at_all.next_delay::<()>(start, &[]), None);
+        let flat = BudgetedExponential {
+            base: Duration::ZERO,
+            multiplier: 2,
+            total_budget: Duration::ZERO,
+        };
+        assert_eq!(flat.next_delay(start, &[((), start)]), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn budget_never_overflows() {
+        use super::{Backoff, BudgetedExponential};
+        use std::time::{Duration, Instant};
+        let policy = BudgetedExponential {
+            base: Duration::from_secs(u64::MAX / 2),
+            multiplier: u32::MAX,
+            total_budget: Duration::MAX,
+        };
+        let start = Instant::now();
+        assert_eq!(
+            policy.next_delay::<()>(start, &[]),
+            Some(Duration::from_secs(u64::MAX / 2))
+        );
+        assert_eq!(policy.next_delay(start, &[((), start)]), None);
+        assert_eq!(policy.next_delay(start, &vec![((), start); 100]), None);
+        let history = [((), start + Duration::from_secs(5))];
+        let tiny = BudgetedExponential {
+            base: Duration::from_millis(1),
+            multiplier: 1,
+            total_budget: Duration::from_millis(1),
+        };
+        assert_eq!(
+            tiny.next_delay(start, &history),
+            Some(Duration::from_millis(1))
+        );
+    }
+
+    #[test]
+    fn hot_reload_changes_params_mid_run() {
+        use super::{Backoff, ExponentialBackoff, HotReload};
+        use std::time::{Duration, Instant};
+        let policy = HotReload::new(ExponentialBackoff::DEFAULT);
+        let operator = policy.clone();
+        let start = Instant::now();
+        let mut history = Vec::new();
+
+        assert_eq!(
+            policy.next_delay(start, &history),
+            Some(Duration::from_millis(100))
+        );
+        history.push(((), start));
+
+        operator.store(ExponentialBackoff {
+            base: Duration::from_secs(1),
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        });
+        assert_eq!(policy.load().max_attempts, 3);
+        assert_eq!(
+            policy.next_delay(start, &history),
+            Some(Duration::from_secs(2))
+        );
+        history.push(((), start));
+        assert_eq!(policy.next_delay(start, &history), None);
+    }
+
+    #[allow(dead_code)]
+    #[derive(Clone, Copy)]
+    struct Weighted(f64);
+
+    impl Retryable for Weighted {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            super::Backoff::next_delay_for(
+                &super::ExponentialBackoff::DEFAULT,
+                self,
+                my_time,
+                previous_retriable_failures,
+            )
+        }
+
+        fn severity(&self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn severity_scales_the_wait() {
+        use super::{scaled_by_severity, ConstRetry};
+        use std::time::{Duration, Instant};
+        let now = Instant::now();
+        let one_before = [(Weighted(1.0), now)];
+        assert_eq!(
+            Weighted(1.0).wait_time(now, &one_before),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            Weighted(4.0).wait_time(now, &one_before),
+            Some(Duration::from_millis(800))
+        );
+        assert_eq!(
+            Weighted(0.5).wait_time(now, &one_before),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            Weighted(1e12).wait_time(now, &[]),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            Weighted(4.0).wait_time(now, &[(Weighted(4.0), now); 4]),
+            None
+        );
+
+        let constant = ConstRetry::<_, 3, 100>(Weighted(3.0));
+        assert_eq!(
+            constant.wait_time(now, &[]),
+            Some(Duration::from_millis(300))
+        );
+
+        let second = Duration::from_secs(1);
+        assert_eq!(scaled_by_severity(second, f64::NAN), second);
+        assert_eq!(scaled_by_severity(second, -1.0), Duration::ZERO);
+        assert_eq!(scaled_by_severity(second, f64::INFINITY), Duration::MAX);
+    }
+
+    #[test]
+    fn severity_stays_within_each_policy() {
+        use super::{
+            Backoff, BudgetedExponential, ExponentialBackoff, FailureWindow, HotReload,
+            MaxAttempts, RateBasedBackoff,
+        };
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        let now = Instant::now();
+        let severe = Weighted(1000.0);
+
+        let budgeted = BudgetedExponential::DEFAULT;
+        assert_eq!(
+            budgeted.next_delay_for(&Weighted(2.0), now, &[]),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            budgeted.next_delay_for(&Weighted(300.0), now, &[]),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(budgeted.next_delay_for(&Weighted(301.0), now, &[]), None);
+        assert_eq!(
+            budgeted.next_delay(now, &[(severe, now - Duration::from_secs(29))]),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            budgeted.next_delay_for(
+                &Weighted(6.0),
+                now,
+                &[(severe, now - Duration::from_secs(29))]
+            ),
+            None
+        );
+
+        let window = Arc::new(FailureWindow::new(10));
+        window.record_success();
+        let rate_based = RateBasedBackoff {
+            window,
+            base: Duration::from_millis(100),
+            max_wait: Duration::from_secs(1),
+            max_attempts: 3,
+        };
+        assert_eq!(
+            rate_based.next_delay_for(&Weighted(2.0), now, &[]),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            rate_based.next_delay_for(&severe, now, &[]),
+            Some(Duration::from_secs(1))
+        );
+
+        let capped = MaxAttempts {
+            inner: ExponentialBackoff::DEFAULT,
+            max_attempts: 3,
+        };
+        assert_eq!(
+            capped.next_delay_for(&severe, now, &[]),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            capped.next_delay_for(&severe, now, &[(severe, now); 2]),
+            None
+        );
+
+        let reloadable = HotReload::new(ExponentialBackoff::DEFAULT);
+        assert_eq!(
+            reloadable.next_delay_for(&severe, now, &[]),
+            Some(Duration::from_secs(30))
+        );
+        reloadable.store(ExponentialBackoff {
+            max_wait: Duration::from_secs(5),
+            ..ExponentialBackoff::DEFAULT
+        });
+        assert_eq!(
+            reloadable.next_delay_for(&severe, now, &[]),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, PartialEq)]
+    struct ServerError(u16);
+
+    impl Retryable for ServerError {
+        type FatalError = u16;
+
+        fn to_fatal(self) -> Self::FatalError {
+            self.0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    #[test]
+    fn repeated_problem_gives_up() {
+        use super::{ExponentialBackoff, GiveUpOnRepeat};
+        use std::time::{Duration, Instant};
+        let fallback = ExponentialBackoff {
+            base: Duration::from_millis(100),
+            max_attempts: 10,
+            ..ExponentialBackoff::DEFAULT
+        };
+        let now = Instant::now();
+        let exact = |code| GiveUpOnRepeat::new(ServerError(code), fallback, 3);
+        let history = [(exact(502), now), (exact(503), now), (exact(503), now)];
+        assert_eq!(exact(503).in_a_row(&history), 3);
+        assert_eq!(exact(503).wait_time(now, &history), None);
+        assert_eq!(
+            exact(500).wait_time(now, &history),
+            Some(Duration::from_millis(800))
+        );
+
+        let same_kind = |a: &ServerError, b: &ServerError| a.0 / 100 == b.0 / 100;
+        let by_kind = |code| GiveUpOnRepeat::with_same(ServerError(code), fallback, 3, same_kind);
+        let history = [
+            (by_kind(404), now),
+            (by_kind(502), now),
+            (by_kind(503), now),
+        ];
+        assert_eq!(by_kind(500).in_a_row(&history), 3);
+        assert_eq!(by_kind(500).wait_time(now, &history), None);
+        assert_eq!(
+            by_kind(429).wait_time(now, &history),
+            Some(Duration::from_millis(800))
+        );
+        assert_eq!(by_kind(500).to_fatal(), 500);
+    }
+}
@@ -0,0 +1,108 @@
+//! retrying in codebases where every error is already a boxed `dyn Error`
+//!     such as anything built around `anyhow`
+//! there is no typed error enum to implement `Retryable` on
+//!     so a classifier decides which errors are recoverable, usually by downcasting
+
+use crate::backoff::{Backoff, ExponentialBackoff};
+use crate::retryable::{Retryable, RetryableResult};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+pub type BoxedError = Box<dyn Error + Send + Sync>;
+
+/// a boxed error that the classifier said was recoverable
+/// how long to wait and when to give up is up to `policy`
+pub struct BoxedRetryable<B = ExponentialBackoff>
+where
+    B: Backoff,
+{
+    pub error: BoxedError,
+    pub policy: B,
+}
+
+impl<B> Retryable for BoxedRetryable<B>
+where
+    B: Backoff,
+{
+    type FatalError = BoxedError;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.error
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        self.policy.next_delay(my_time, previous_retriable_failures)
+    }
+}
+
+pub fn classify_boxed<T, E, C, B>(
+    result: Result<T, E>,
+    is_recoverable: C,
+    policy: B,
+) -> RetryableResult<T, BoxedRetryable<B>, BoxedError>
+where
+    E: Into<BoxedError>,
+    C: FnOnce(&(dyn Error + 'static)) -> bool,
+    B: Backoff,
+{
+    //! turn the result of one attempt into the three way outcome
+    //! errors `is_recoverable` accepts become `Retryable` with `policy` deciding the waits
+    //!     and all other errors are `Fatal`
+    match result {
+        Ok(t) => RetryableResult::GoodResult(t),
+        Err(e) => {
+            let error = e.into();
+            if is_recoverable(&*error) {
+                RetryableResult::Retryable(BoxedRetryable { error, policy })
+            } else {
+                RetryableResult::Fatal(error)
+            }
+        }
+    }
+}
+
+mod test {
+
+    #[test]
+    fn classify_by_downcast() {
+        use super::classify_boxed;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::{Retryable, RetryableResult};
+        use std::time::{Duration, Instant};
+        let policy = || ExponentialBackoff {
+            base: Duration::from_millis(10),
+            multiplier: 2,
+            max_wait: Duration::from_secs(1),
+            max_attempts: 3,
+        };
+        let is_io = |e: &(dyn std::error::Error + 'static)| e.is::<std::io::Error>();
+
+        let io_failure: Result<(), std::io::Error> =
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        let RetryableResult::Retryable(recoverable) = classify_boxed(io_failure, is_io, policy())
+        else {
+            panic!("io errors are recoverable here");
+        };
+        assert_eq!(
+            recoverable.wait_time(Instant::now(), &[]),
+            Some(Duration::from_millis(10))
+        );
+        assert!(recoverable.to_fatal().is::<std::io::Error>());
+
+        let parse_failure = "x".parse::<u8>();
+        assert!(matches!(
+            classify_boxed(parse_failure, is_io, policy()),
+            RetryableResult::Fatal(e) if e.is::<std::num::ParseIntError>()
+        ));
+        assert!(matches!(
+            classify_boxed(Ok::<_, std::io::Error>(5), is_io, policy()),
+            RetryableResult::GoodResult(5)
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+//! why a retry loop stopped without a success
+//!     for operators trying to tune retry behaviour from logs
+
+/// the reason a retry loop gave up
+/// more reasons get added as the drivers get more ways to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GiveUpReason {
+    /// one of the attempts gave a fatal error directly
+    Fatal,
+    /// the recoverable error's `wait_decision` said to give up
+    PolicyGaveUp,
+    /// the health check said the dependency is unhealthy so there was no point retrying
+    Unhealthy,
+    /// an async check on the recoverable error said not to retry it
+    Vetoed,
+    /// waiting for the next attempt would have gone past the deadline
+    DeadlineExceeded,
+    /// in strict mode, the policy gave up on the very first recoverable error
+    ///     which usually means a `wait_time` that always says None by mistake
+    NoRetryAllowed,
+    /// the next attempt would have started while retries are not allowed
+    ///     such as outside of business hours for a batch job
+    OutsideWindow,
+    /// the builder's `max_attempts` cap was reached
+    ///     even though the recoverable error's `wait_decision` would still have retried
+    AttemptsExhausted,
+}
+
+/// the fatal error a retry loop ended with, along with why and after how many attempts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GaveUp<F> {
+    pub error: F,
+    pub reason: GiveUpReason,
+    pub attempts: usize,
+}
+
+impl<F> GaveUp<F> {
+    pub fn into_error(self) -> F {
+        //! just the fatal error, for callers who do not care why
+        self.error
+    }
+}
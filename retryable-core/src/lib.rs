@@ -0,0 +1,24 @@
+//! the pure decision logic of when to try again
+//! there is no async and no runtime here, only the types and the policies
+//!     so that different drivers can be built on top of the same core
+//! `retryable-result` has the async driver `repeatedly_try`
+
+pub mod backoff;
+#[cfg(feature = "boxed-error")]
+pub mod boxed;
+pub mod give_up;
+pub mod retryable;
+pub mod simulate;
+pub mod test_util;
+
+pub use backoff::{
+    next_backoff, next_backoff_with_jitter, scaled_by_severity, Backoff, BudgetedExponential,
+    ConstRetry, ExponentialBackoff, FailureWindow, GiveUpOnRepeat, HotReload, MaxAttempts,
+    RateBasedBackoff, RateLimitReset, RateLimited, RetryConfig,
+};
+pub use give_up::{GaveUp, GiveUpReason};
+pub use retryable::{
+    relative_history, RetryNotGood, Retryable, RetryableResult, RetryableResultExt, WaitDecision,
+};
+pub use simulate::{simulate, RetrySimulation, SIMULATION_LIMIT};
+pub use test_util::{assert_backoff_schedule, backoff_schedule};
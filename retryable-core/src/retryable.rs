@@ -0,0 +1,880 @@
+use std::{
+    fmt::{Debug, Display},
+    time::{Duration, Instant},
+};
+
+#[allow(clippy::module_name_repetitions)]
+pub trait Retryable
+where
+    Self: Sized,
+{
+    //! put the logic of how to handle recoverable errors into the `wait_time` function
+    //! one of the tests shows the pattern of exponential backoff with a hard cutoff
+    //! but it does not take into account what the recoverable errors were
+    //! another implementation of this trait might look to see if the same recoverable error
+    //! was the common cause and decide to give up if it that is the case
+    //! when writing `wait_time` by hand, be careful with arithmetic on `Duration`
+    //!     `Duration` multiplication and addition panic on overflow
+    //!     so use `saturating_mul`/`checked_mul` and cap at some maximum
+    //!     or delegate to one of the policies in the `backoff` module which already do that
+    type FatalError;
+    fn to_fatal(self) -> Self::FatalError;
+
+    fn to_fatal_ref(&self) -> Self::FatalError
+    where
+        Self: Clone,
+    {
+        //! the fatal form without giving up the recoverable error
+        //!     so the same error can still be logged or kept in a history afterwards
+        //! by default it is `to_fatal` on a clone
+        //!     override it when the fatal form only needs part of the error
+        //!     and cloning the whole thing is expensive
+        self.clone().to_fatal()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration>;
+
+    fn wait_decision(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> WaitDecision {
+        //! what the driver actually consults
+        //! by default it is exactly `wait_time`
+        //! override this for errors that should sometimes be retried right away
+        //!     such as an optimistic lock conflict, where waiting is counterproductive
+        WaitDecision::from(self.wait_time(my_time, previous_retriable_failures))
+    }
+
+    fn timed_wait_decision(
+        &self,
+        my_time: Instant,
+        my_attempt_duration: Duration,
+        previous_retriable_failures: &[(Self, Instant)],
+        previous_attempt_durations: &[Duration],
+    ) -> WaitDecision {
+        //! what the driver actually consults, with how long each attempt took before it failed
+        //!     `previous_attempt_durations` lines up with `previous_retriable_failures`
+        //! by default the durations are ignored and it is exactly `wait_decision`
+        //! override this to react differently to slow failures (likely overload)
+        //!     than to instant failures (likely a bad request)
+        let _ = (my_attempt_duration, previous_attempt_durations);
+        self.wait_decision(my_time, previous_retriable_failures)
+    }
+
+    fn resets_history(&self) -> bool {
+        //! true when this error is a fresh problem, so the failures before it should not count against it
+        //!     such as a blip on a long lived stream an hour after the last one
+        //! the driver then forgets the earlier failures before deciding how long to wait
+        //!     and they are not replayed to the loggers if the loop later gives up
+        //! by default false, so every failure counts
+        false
+    }
+
+    fn severity(&self) -> f64 {
+        //! how hard to back off from this error compared to others of the same type
+        //!     such as 4.0 for a rate limit and 0.5 for a DNS blip
+        //! the policies in the `backoff` module multiply it into their wait
+        //!     when asked through `Backoff::next_delay_for`
+        //! by default 1.0, which leaves every wait as it was
+        1.0
+    }
+
+    fn escalate(self, previous_retriable_failures: &[(Self, Instant)]) -> Self::FatalError {
+        //! the fatal error when the driver gives up because the policy ran out of retries
+        //!     as opposed to `to_fatal` which is every other way a recoverable error becomes fatal
+        //! override this to make the exhaustion error louder, such as carrying
+        //!     how many times it failed and over how long, for alerting
+        //! by default it is exactly `to_fatal`
+        let _ = previous_retriable_failures;
+        self.to_fatal()
+    }
+}
+
+/// a borrowed recoverable error can be used wherever an owned one can
+///     such as in a `RetryableResult<&T, &R, F>` made for inspecting errors without moving them
+/// the fatal error is `to_fatal_ref` of the error behind the reference
+/// `R` only knows how to read a history of owned errors
+///     so every decision clones the history, which is fine for inspection but not for a hot loop
+impl<R> Retryable for &R
+where
+    R: Retryable + Clone,
+{
+    type FatalError = R::FatalError;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.to_fatal_ref()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        (**self).wait_time(my_time, &owned_history(previous_retriable_failures))
+    }
+
+    fn wait_decision(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> WaitDecision {
+        (**self).wait_decision(my_time, &owned_history(previous_retriable_failures))
+    }
+
+    fn timed_wait_decision(
+        &self,
+        my_time: Instant,
+        my_attempt_duration: Duration,
+        previous_retriable_failures: &[(Self, Instant)],
+        previous_attempt_durations: &[Duration],
+    ) -> WaitDecision {
+        (**self).timed_wait_decision(
+            my_time,
+            my_attempt_duration,
+            &owned_history(previous_retriable_failures),
+            previous_attempt_durations,
+        )
+    }
+
+    fn resets_history(&self) -> bool {
+        (**self).resets_history()
+    }
+
+    fn severity(&self) -> f64 {
+        (**self).severity()
+    }
+
+    fn escalate(self, previous_retriable_failures: &[(Self, Instant)]) -> Self::FatalError {
+        self.clone()
+            .escalate(&owned_history(previous_retriable_failures))
+    }
+}
+
+fn owned_history<R: Clone>(borrowed: &[(&R, Instant)]) -> Vec<(R, Instant)> {
+    borrowed
+        .iter()
+        .map(|(r, when)| ((*r).clone(), *when))
+        .collect()
+}
+
+#[must_use]
+pub fn relative_history<R>(previous_retriable_failures: &[(R, Instant)]) -> Vec<(&R, Duration)> {
+    //! the history with each failure's time as how long after the first failure it came
+    //!     so the first one is at zero, for a `wait_time` that reasons about gaps and spans
+    //! an out of order time counts as zero instead of panicking
+    //! for the current failure, `my_time.saturating_duration_since` the first one's `Instant`
+    let Some((_, first)) = previous_retriable_failures.first() else {
+        return Vec::new();
+    };
+    previous_retriable_failures
+        .iter()
+        .map(|(r, when)| (r, when.saturating_duration_since(*first)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitDecision {
+    /// sleep for this long and then try again
+    Wait(Duration),
+    /// try again right away without sleeping at all
+    /// this still counts as a recoverable failure in the history
+    ///     so attempt caps that look at the history still apply
+    Immediate,
+    /// no more tries, convert to the fatal error
+    GiveUp,
+}
+
+impl WaitDecision {
+    #[must_use]
+    pub fn immediate_unless_give_up(wait: Option<Duration>) -> Self {
+        //! keep the give up decision of some policy but skip the wait it asked for
+        //!     `WaitDecision::immediate_unless_give_up(POLICY.next_delay(my_time, previous))`
+        //!     retries right away until that policy's attempt cap is hit
+        match wait {
+            Some(_) => WaitDecision::Immediate,
+            None => WaitDecision::GiveUp,
+        }
+    }
+}
+
+impl From<Option<Duration>> for WaitDecision {
+    fn from(wait: Option<Duration>) -> Self {
+        match wait {
+            Some(how_long) => WaitDecision::Wait(how_long),
+            None => WaitDecision::GiveUp,
+        }
+    }
+}
+
+/// with the `serde` feature it is (de)serialized adjacently tagged
+///     `{"outcome": "good", "value": ...}`, `{"outcome": "retryable", "value": ...}`
+///     or `{"outcome": "fatal", "value": ...}`
+/// those names are part of the wire format and will not change
+///     so an outcome can be sent to another service and still say it was worth retrying
+#[allow(clippy::module_name_repetitions)]
+#[allow(dead_code)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "outcome", content = "value", rename_all = "snake_case")
+)]
+pub enum RetryableResult<T, R, F>
+where
+    R: Retryable<FatalError = F> + Sized,
+    T: Sized,
+    F: Sized,
+{
+    #[cfg_attr(feature = "serde", serde(rename = "good"))]
+    GoodResult(T),
+    Retryable(R),
+    Fatal(F),
+}
+
+impl<T, R, F> RetryableResult<T, R, F>
+where
+    R: Retryable<FatalError = F>,
+{
+    #[track_caller]
+    pub fn unwrap(self) -> T
+    where
+        R: Debug,
+        F: Debug,
+    {
+        //! the success value
+        //! # Panics
+        //! if this was either kind of error, with that error in the panic message
+        //!     and the location of the caller
+        match self {
+            RetryableResult::GoodResult(t) => t,
+            RetryableResult::Retryable(r) => {
+                panic!("called `RetryableResult::unwrap()` on a `Retryable` value: {r:?}")
+            }
+            RetryableResult::Fatal(f) => {
+                panic!("called `RetryableResult::unwrap()` on a `Fatal` value: {f:?}")
+            }
+        }
+    }
+
+    #[track_caller]
+    pub fn expect(self, msg: &str) -> T
+    where
+        R: Debug,
+        F: Debug,
+    {
+        //! the success value
+        //! # Panics
+        //! if this was either kind of error, with `msg` and that error in the panic message
+        //!     and the location of the caller
+        match self {
+            RetryableResult::GoodResult(t) => t,
+            RetryableResult::Retryable(r) => panic!("{msg}: {r:?}"),
+            RetryableResult::Fatal(f) => panic!("{msg}: {f:?}"),
+        }
+    }
+
+    pub fn or(self, default: T) -> T {
+        //! the success value, or `default` if this was either kind of error
+        //! the error is dropped
+        match self {
+            RetryableResult::GoodResult(t) => t,
+            RetryableResult::Retryable(_) | RetryableResult::Fatal(_) => default,
+        }
+    }
+
+    pub fn or_else<G: FnOnce() -> T>(self, g: G) -> T {
+        //! the success value, or what `g` makes if this was either kind of error
+        //! `g` is only called when it is needed
+        match self {
+            RetryableResult::GoodResult(t) => t,
+            RetryableResult::Retryable(_) | RetryableResult::Fatal(_) => g(),
+        }
+    }
+
+    pub fn unwrap_or_default(self) -> T
+    where
+        T: Default,
+    {
+        //! the success value, or `T::default()` if this was either kind of error
+        //! the error is dropped
+        self.or_else(T::default)
+    }
+
+    pub fn map_or<U, G: FnOnce(T) -> U>(self, default: U, g: G) -> U {
+        //! `g` of the success value, or `default` if this was either kind of error
+        match self {
+            RetryableResult::GoodResult(t) => g(t),
+            RetryableResult::Retryable(_) | RetryableResult::Fatal(_) => default,
+        }
+    }
+
+    pub fn map_or_else<U, D: FnOnce() -> U, G: FnOnce(T) -> U>(self, default: D, g: G) -> U {
+        //! `g` of the success value, or what `default` makes if this was either kind of error
+        match self {
+            RetryableResult::GoodResult(t) => g(t),
+            RetryableResult::Retryable(_) | RetryableResult::Fatal(_) => default(),
+        }
+    }
+
+    pub fn classify_for_state<S, G, H, K>(self, on_good: G, on_retryable: H, on_fatal: K) -> S
+    where
+        G: FnOnce(T) -> S,
+        H: FnOnce(R) -> S,
+        K: FnOnce(F) -> S,
+    {
+        //! turn each variant into the next state of a hand written `Future` or `Stream`
+        //!     for state machines that do their own retrying instead of using the provided loops
+        //! typically `on_good` goes to a done state, `on_fatal` to a failed state
+        //!     and `on_retryable` to a waiting state that stores the error
+        //!     along with the `wait_decision` it gives for the history so far
+        match self {
+            RetryableResult::GoodResult(t) => on_good(t),
+            RetryableResult::Retryable(r) => on_retryable(r),
+            RetryableResult::Fatal(f) => on_fatal(f),
+        }
+    }
+
+    #[must_use]
+    pub fn inspect<G: FnOnce(&T)>(self, g: G) -> Self {
+        //! call `g` on the success value if there is one, for logging or instrumenting in a chain
+        //! gives back `self` unchanged
+        if let RetryableResult::GoodResult(t) = &self {
+            g(t);
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn inspect_retryable<G: FnOnce(&R)>(self, g: G) -> Self {
+        //! call `g` on the recoverable error if there is one
+        //! gives back `self` unchanged
+        if let RetryableResult::Retryable(r) = &self {
+            g(r);
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn inspect_fatal<G: FnOnce(&F)>(self, g: G) -> Self {
+        //! call `g` on the fatal error if there is one
+        //! gives back `self` unchanged
+        if let RetryableResult::Fatal(f) = &self {
+            g(f);
+        }
+        self
+    }
+
+    pub fn contains(&self, x: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        //! whether this is a `GoodResult` equal to `x`
+        matches!(self, RetryableResult::GoodResult(t) if t == x)
+    }
+
+    pub fn zip<U>(self, other: RetryableResult<U, R, F>) -> RetryableResult<(T, U), R, F> {
+        //! both successes together, the same as `Option::zip`
+        //! when `self` is not a `GoodResult` its error is the outcome, whatever `other` was
+        //!     so a `Retryable` here beats a `Fatal` in `other`
+        //! otherwise when `other` is not a `GoodResult` its error is the outcome
+        match (self, other) {
+            (RetryableResult::GoodResult(t), RetryableResult::GoodResult(u)) => {
+                RetryableResult::GoodResult((t, u))
+            }
+            (RetryableResult::Retryable(r), _)
+            | (RetryableResult::GoodResult(_), RetryableResult::Retryable(r)) => {
+                RetryableResult::Retryable(r)
+            }
+            (RetryableResult::Fatal(f), _)
+            | (RetryableResult::GoodResult(_), RetryableResult::Fatal(f)) => {
+                RetryableResult::Fatal(f)
+            }
+        }
+    }
+
+    pub fn contains_fatal(&self, f: &F) -> bool
+    where
+        F: PartialEq,
+    {
+        //! whether this is a `Fatal` equal to `f`
+        matches!(self, RetryableResult::Fatal(e) if e == f)
+    }
+}
+
+impl<T, R, F> RetryableResult<RetryableResult<T, R, F>, R, F>
+where
+    R: Retryable<FatalError = F>,
+{
+    pub fn flatten(self) -> RetryableResult<T, R, F> {
+        //! collapse one level of nesting
+        //! if the outer one is `GoodResult` then the inner one is the outcome
+        //! otherwise the outer `Retryable` or `Fatal` wins
+        match self {
+            RetryableResult::GoodResult(inner) => inner,
+            RetryableResult::Retryable(r) => RetryableResult::Retryable(r),
+            RetryableResult::Fatal(f) => RetryableResult::Fatal(f),
+        }
+    }
+}
+
+impl<T, R, F> RetryableResult<Option<T>, R, F>
+where
+    R: Retryable<FatalError = F>,
+{
+    pub fn transpose(self) -> Option<RetryableResult<T, R, F>> {
+        //! the same as `Result::transpose`
+        //! a `GoodResult(None)` is None, and everything else is `Some`
+        //!     with the success unwrapped from its `Option` and either kind of error as it was
+        match self {
+            RetryableResult::GoodResult(None) => None,
+            RetryableResult::GoodResult(Some(t)) => Some(RetryableResult::GoodResult(t)),
+            RetryableResult::Retryable(r) => Some(RetryableResult::Retryable(r)),
+            RetryableResult::Fatal(f) => Some(RetryableResult::Fatal(f)),
+        }
+    }
+}
+
+/// either of the ways a `RetryableResult` can fail to be a `GoodResult`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryNotGood<R, F> {
+    Retryable(R),
+    Fatal(F),
+}
+
+impl<R, F> Display for RetryNotGood<R, F>
+where
+    R: Display,
+    F: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetryNotGood::Retryable(r) => write!(f, "recoverable error: {r}"),
+            RetryNotGood::Fatal(fatal) => write!(f, "fatal error: {fatal}"),
+        }
+    }
+}
+
+impl<R, F> std::error::Error for RetryNotGood<R, F>
+where
+    R: Debug + Display,
+    F: Debug + Display,
+{
+}
+
+impl<T, R, F> From<RetryableResult<T, R, F>> for Result<T, RetryNotGood<R, F>>
+where
+    R: Retryable<FatalError = F>,
+{
+    fn from(outcome: RetryableResult<T, R, F>) -> Self {
+        //! for code paths where any non success is an error
+        //!     `let val = Result::from(outcome)?;`
+        //! the orphan rules do not allow `TryFrom<RetryableResult<T, R, F>> for T`
+        //!     because `T` is not a type of this crate, so this is the conversion instead
+        match outcome {
+            RetryableResult::GoodResult(t) => Ok(t),
+            RetryableResult::Retryable(r) => Err(RetryNotGood::Retryable(r)),
+            RetryableResult::Fatal(f) => Err(RetryNotGood::Fatal(f)),
+        }
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub trait RetryableResultExt<T, R, F>: Iterator<Item = RetryableResult<T, R, F>>
+where
+    R: Retryable<FatalError = F>,
+    Self: Sized,
+{
+    //! for iterators of outcomes, such as the results of a batch
+
+    fn first_fatal(mut self) -> Option<F> {
+        //! stops at the first `Fatal` and gives its error
+        //! None if there was no `Fatal` at all
+        self.find_map(|outcome| match outcome {
+            RetryableResult::Fatal(f) => Some(f),
+            _ => None,
+        })
+    }
+
+    fn partition_outcomes(self) -> (Vec<T>, Vec<R>, Vec<F>) {
+        //! the successes, recoverable errors and fatal errors each in their original order
+        let mut good = Vec::new();
+        let mut retryable = Vec::new();
+        let mut fatal = Vec::new();
+        for outcome in self {
+            match outcome {
+                RetryableResult::GoodResult(t) => good.push(t),
+                RetryableResult::Retryable(r) => retryable.push(r),
+                RetryableResult::Fatal(f) => fatal.push(f),
+            }
+        }
+        (good, retryable, fatal)
+    }
+}
+
+impl<I, T, R, F> RetryableResultExt<T, R, F> for I
+where
+    I: Iterator<Item = RetryableResult<T, R, F>>,
+    R: Retryable<FatalError = F>,
+{
+}
+
+mod test {
+    use super::Retryable;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct AlwaysGiveUp(u8);
+
+    impl Retryable for AlwaysGiveUp {
+        type FatalError = u8;
+
+        fn to_fatal(self) -> Self::FatalError {
+            self.0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    #[test]
+    fn fatal_form_by_reference() {
+        #[derive(Clone)]
+        struct Throttled(&'static str);
+        impl Retryable for Throttled {
+            type FatalError = String;
+            fn to_fatal(self) -> Self::FatalError {
+                format!("throttled by {}", self.0)
+            }
+            fn wait_time(
+                &self,
+                _my_time: std::time::Instant,
+                _previous_retriable_failures: &[(Self, std::time::Instant)],
+            ) -> Option<std::time::Duration> {
+                None
+            }
+        }
+        let error = Throttled("gateway");
+        assert_eq!(error.to_fatal_ref(), "throttled by gateway");
+        assert_eq!(error.to_fatal(), "throttled by gateway");
+    }
+
+    #[test]
+    fn borrowed_errors_decide_like_owned() {
+        use super::{RetryableResult, WaitDecision};
+        #[derive(Clone, Debug, PartialEq)]
+        struct Busy(u8);
+        impl Retryable for Busy {
+            type FatalError = u8;
+            fn to_fatal(self) -> Self::FatalError {
+                self.0
+            }
+            fn wait_time(
+                &self,
+                _my_time: std::time::Instant,
+                previous_retriable_failures: &[(Self, std::time::Instant)],
+            ) -> Option<std::time::Duration> {
+                (previous_retriable_failures.len() < 2).then_some(std::time::Duration::from_secs(1))
+            }
+        }
+        let now = std::time::Instant::now();
+        let errors = [Busy(1), Busy(2), Busy(3)];
+        let history: Vec<_> = errors.iter().map(|e| (e, now)).collect();
+        assert_eq!(
+            <&Busy as Retryable>::wait_decision(&&errors[2], now, &history[..1]),
+            WaitDecision::Wait(std::time::Duration::from_secs(1))
+        );
+        assert_eq!(
+            <&Busy as Retryable>::wait_decision(&&errors[2], now, &history[..2]),
+            WaitDecision::GiveUp
+        );
+        let borrowed: RetryableResult<(), &Busy, u8> = RetryableResult::Retryable(&errors[0]);
+        assert!(matches!(borrowed, RetryableResult::Retryable(Busy(1))));
+        assert_eq!(<&Busy as Retryable>::to_fatal(&errors[1]), 2);
+        assert_eq!(errors[1], Busy(2));
+    }
+
+    #[test]
+    fn flatten() {
+        use super::RetryableResult;
+        type Nested = RetryableResult<RetryableResult<u8, AlwaysGiveUp, u8>, AlwaysGiveUp, u8>;
+        let good: Nested = RetryableResult::GoodResult(RetryableResult::GoodResult(1));
+        assert!(matches!(good.flatten(), RetryableResult::GoodResult(1)));
+        let inner_retryable: Nested =
+            RetryableResult::GoodResult(RetryableResult::Retryable(AlwaysGiveUp(2)));
+        assert!(matches!(
+            inner_retryable.flatten(),
+            RetryableResult::Retryable(AlwaysGiveUp(2))
+        ));
+        let inner_fatal: Nested = RetryableResult::GoodResult(RetryableResult::Fatal(3));
+        assert!(matches!(inner_fatal.flatten(), RetryableResult::Fatal(3)));
+        let outer_retryable: Nested = RetryableResult::Retryable(AlwaysGiveUp(4));
+        assert!(matches!(
+            outer_retryable.flatten(),
+            RetryableResult::Retryable(AlwaysGiveUp(4))
+        ));
+        let outer_fatal: Nested = RetryableResult::Fatal(5);
+        assert!(matches!(outer_fatal.flatten(), RetryableResult::Fatal(5)));
+    }
+
+    #[test]
+    fn unwrap_and_expect() {
+        use super::RetryableResult;
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(1);
+        assert_eq!(good.unwrap(), 1);
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(2);
+        assert_eq!(good.expect("was good"), 2);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "called `RetryableResult::unwrap()` on a `Retryable` value: AlwaysGiveUp(3)"
+    )]
+    fn unwrap_retryable_panics() {
+        use super::RetryableResult;
+        RetryableResult::<u8, AlwaysGiveUp, u8>::Retryable(AlwaysGiveUp(3)).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "needed it: 4")]
+    fn expect_fatal_panics() {
+        use super::RetryableResult;
+        RetryableResult::<u8, AlwaysGiveUp, u8>::Fatal(4).expect("needed it");
+    }
+
+    #[test]
+    fn fallback_values() {
+        use super::RetryableResult;
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(1);
+        assert_eq!(good.or(9), 1);
+        let retryable = RetryableResult::<u8, AlwaysGiveUp, u8>::Retryable(AlwaysGiveUp(2));
+        assert_eq!(retryable.or(9), 9);
+        let fatal = RetryableResult::<u8, AlwaysGiveUp, u8>::Fatal(3);
+        assert_eq!(fatal.or_else(|| 8), 8);
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(4);
+        assert_eq!(good.or_else(|| unreachable!("not needed for a success")), 4);
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(5);
+        assert_eq!(good.unwrap_or_default(), 5);
+        let retryable = RetryableResult::<u8, AlwaysGiveUp, u8>::Retryable(AlwaysGiveUp(6));
+        assert_eq!(retryable.unwrap_or_default(), 0);
+        let fatal = RetryableResult::<Vec<u8>, AlwaysGiveUp, u8>::Fatal(7);
+        assert_eq!(fatal.unwrap_or_default(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn into_state_machine() {
+        use super::{RetryableResult, WaitDecision};
+        #[derive(Debug, PartialEq)]
+        enum Download {
+            Done(u8),
+            Backoff(WaitDecision),
+            Failed(u8),
+        }
+        let now = std::time::Instant::now();
+        let step = |outcome: RetryableResult<u8, AlwaysGiveUp, u8>| {
+            outcome.classify_for_state(
+                Download::Done,
+                |r| Download::Backoff(r.wait_decision(now, &[])),
+                Download::Failed,
+            )
+        };
+        assert_eq!(step(RetryableResult::GoodResult(1)), Download::Done(1));
+        assert_eq!(
+            step(RetryableResult::Retryable(AlwaysGiveUp(2))),
+            Download::Backoff(WaitDecision::GiveUp)
+        );
+        assert_eq!(step(RetryableResult::Fatal(3)), Download::Failed(3));
+    }
+
+    #[test]
+    fn map_or_collapses_errors() {
+        use super::RetryableResult;
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(2);
+        assert_eq!(good.map_or(0, |t| t * 10), 20);
+        let retryable = RetryableResult::<u8, AlwaysGiveUp, u8>::Retryable(AlwaysGiveUp(3));
+        assert_eq!(retryable.map_or(0, |t| t * 10), 0);
+        let fatal = RetryableResult::<u8, AlwaysGiveUp, u8>::Fatal(4);
+        assert_eq!(fatal.map_or_else(|| 1, |t| t * 10), 1);
+        let good = RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(5);
+        assert_eq!(
+            good.map_or_else(|| unreachable!("not needed for a success"), |t| t * 10),
+            50
+        );
+    }
+
+    #[test]
+    fn iterator_outcomes() {
+        use super::{RetryableResult, RetryableResultExt};
+        let batch = || {
+            vec![
+                RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(1),
+                RetryableResult::Retryable(AlwaysGiveUp(2)),
+                RetryableResult::Fatal(3),
+                RetryableResult::GoodResult(4),
+                RetryableResult::Fatal(5),
+            ]
+            .into_iter()
+        };
+        assert_eq!(batch().first_fatal(), Some(3));
+        assert_eq!(batch().take(2).first_fatal(), None);
+        let (good, retryable, fatal) = batch().partition_outcomes();
+        assert_eq!(good, vec![1, 4]);
+        assert_eq!(retryable.len(), 1);
+        assert_eq!(fatal, vec![3, 5]);
+    }
+
+    #[test]
+    fn into_result_of_not_good() {
+        use super::{RetryNotGood, RetryableResult};
+        fn extract(
+            outcome: RetryableResult<u8, AlwaysGiveUp, u8>,
+        ) -> Result<u8, RetryNotGood<AlwaysGiveUp, u8>> {
+            let val = Result::from(outcome)?;
+            Ok(val + 1)
+        }
+        assert!(matches!(extract(RetryableResult::GoodResult(1)), Ok(2)));
+        assert!(matches!(
+            extract(RetryableResult::Retryable(AlwaysGiveUp(2))),
+            Err(RetryNotGood::Retryable(AlwaysGiveUp(2)))
+        ));
+        assert!(matches!(
+            extract(RetryableResult::Fatal(3)),
+            Err(RetryNotGood::Fatal(3))
+        ));
+        let not_good = RetryNotGood::<u8, &str>::Fatal("gone");
+        assert_eq!(not_good.to_string(), "fatal error: gone");
+    }
+
+    #[test]
+    fn inspect_each_variant() {
+        use super::RetryableResult;
+        let mut seen = Vec::new();
+        for outcome in [
+            RetryableResult::<u8, AlwaysGiveUp, u8>::GoodResult(1),
+            RetryableResult::Retryable(AlwaysGiveUp(2)),
+            RetryableResult::Fatal(3),
+        ] {
+            let _ = outcome
+                .inspect(|t| seen.push(("good", *t)))
+                .inspect_retryable(|r| seen.push(("retryable", r.0)))
+                .inspect_fatal(|f| seen.push(("fatal", *f)));
+        }
+        assert_eq!(seen, vec![("good", 1), ("retryable", 2), ("fatal", 3)]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_outcomes_are_tagged() {
+        use super::RetryableResult;
+        type Outcome = RetryableResult<String, AlwaysGiveUp, u8>;
+        let cases = [
+            (
+                Outcome::GoodResult("done".to_string()),
+                r#"{"outcome":"good","value":"done"}"#,
+            ),
+            (
+                Outcome::Retryable(AlwaysGiveUp(2)),
+                r#"{"outcome":"retryable","value":2}"#,
+            ),
+            (Outcome::Fatal(3), r#"{"outcome":"fatal","value":3}"#),
+        ];
+        for (outcome, wire) in cases {
+            assert_eq!(serde_json::to_string(&outcome).unwrap(), wire);
+            let back: Outcome = serde_json::from_str(wire).unwrap();
+            assert_eq!(
+                serde_json::to_string(&back).unwrap(),
+                wire,
+                "round trip of {wire}"
+            );
+        }
+        assert!(serde_json::from_str::<Outcome>(r#"{"outcome":"maybe","value":1}"#).is_err());
+    }
+
+    #[test]
+    fn transpose_optional_success() {
+        use super::RetryableResult;
+        type Maybe = RetryableResult<Option<u8>, AlwaysGiveUp, u8>;
+        assert!(Maybe::GoodResult(None).transpose().is_none());
+        assert!(matches!(
+            Maybe::GoodResult(Some(1)).transpose(),
+            Some(RetryableResult::GoodResult(1))
+        ));
+        assert!(matches!(
+            Maybe::Retryable(AlwaysGiveUp(2)).transpose(),
+            Some(RetryableResult::Retryable(AlwaysGiveUp(2)))
+        ));
+        assert!(matches!(
+            Maybe::Fatal(3).transpose(),
+            Some(RetryableResult::Fatal(3))
+        ));
+    }
+
+    #[test]
+    fn contains_exact_values() {
+        use super::RetryableResult;
+        type Outcome = RetryableResult<u8, AlwaysGiveUp, u8>;
+        assert!(Outcome::GoodResult(1).contains(&1));
+        assert!(!Outcome::GoodResult(1).contains(&2));
+        assert!(!Outcome::Fatal(1).contains(&1));
+        assert!(!Outcome::Retryable(AlwaysGiveUp(1)).contains(&1));
+        assert!(Outcome::Fatal(3).contains_fatal(&3));
+        assert!(!Outcome::Fatal(3).contains_fatal(&4));
+        assert!(!Outcome::GoodResult(3).contains_fatal(&3));
+    }
+
+    #[test]
+    fn zip_prefers_the_first_error() {
+        use super::RetryableResult;
+        type Outcome<T> = RetryableResult<T, AlwaysGiveUp, u8>;
+        assert!(matches!(
+            Outcome::GoodResult(1).zip(Outcome::GoodResult('a')),
+            RetryableResult::GoodResult((1, 'a'))
+        ));
+        assert!(matches!(
+            Outcome::GoodResult(1).zip(Outcome::<char>::Fatal(2)),
+            RetryableResult::Fatal(2)
+        ));
+        assert!(matches!(
+            Outcome::<u8>::Retryable(AlwaysGiveUp(3)).zip(Outcome::<char>::Fatal(4)),
+            RetryableResult::Retryable(AlwaysGiveUp(3))
+        ));
+        assert!(matches!(
+            Outcome::<u8>::Fatal(5).zip(Outcome::<char>::Retryable(AlwaysGiveUp(6))),
+            RetryableResult::Fatal(5)
+        ));
+    }
+
+    #[test]
+    fn history_relative_to_first_failure() {
+        use super::relative_history;
+        use std::time::{Duration, Instant};
+        let start = Instant::now();
+        let history = [
+            (AlwaysGiveUp(1), start + Duration::from_millis(50)),
+            (AlwaysGiveUp(2), start + Duration::from_millis(150)),
+            (AlwaysGiveUp(3), start + Duration::from_millis(400)),
+        ];
+        let relative: Vec<_> = relative_history(&history)
+            .into_iter()
+            .map(|(r, offset)| (r.0, offset))
+            .collect();
+        assert_eq!(
+            relative,
+            vec![
+                (1, Duration::ZERO),
+                (2, Duration::from_millis(100)),
+                (3, Duration::from_millis(350)),
+            ]
+        );
+        assert!(relative_history::<AlwaysGiveUp>(&[]).is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+//! a dry run of a backoff policy, for capacity planning and for checking a policy in tests
+//! no attempts are made and nothing sleeps, only the decisions of the policy are replayed
+//!     so a schedule of hours comes back right away
+
+use crate::backoff::Backoff;
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// more recoverable failures than this and the simulation stops without the policy giving up
+///     so that a policy that never gives up, which is a bug, does not hang the simulation
+pub const SIMULATION_LIMIT: usize = 10_000;
+
+/// what a policy would do with a run of recoverable failures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetrySimulation {
+    /// every failure that was retried, with how long the policy waited after it
+    pub waits: Vec<(Instant, Duration)>,
+    /// the failure the policy gave up on
+    ///     None only when `SIMULATION_LIMIT` was reached first
+    pub gave_up_at: Option<Instant>,
+    /// how many attempts were made, each of which failed
+    pub attempts: usize,
+    /// from the first failure to the last one
+    pub total_elapsed: Duration,
+}
+
+impl RetrySimulation {
+    #[must_use]
+    pub fn total_wait(&self) -> Duration {
+        //! the time spent waiting, as opposed to in the attempts themselves
+        self.waits.iter().fold(Duration::ZERO, |total, (_, wait)| {
+            total.saturating_add(*wait)
+        })
+    }
+
+    #[must_use]
+    pub fn dominates(&self, other: &Self) -> bool {
+        //! whether this schedule is at least as cautious as `other` at every step
+        //!     each wait is at least as long as the wait after the same failure in `other`
+        //!     and there are no more retries than in `other`
+        //! for checking that a change to a policy did not make it retry more aggressively
+        //! only the waits are compared, not when the failures happened
+        self.waits.len() <= other.waits.len()
+            && self
+                .waits
+                .iter()
+                .zip(&other.waits)
+                .all(|((_, mine), (_, theirs))| mine >= theirs)
+    }
+
+    #[must_use]
+    pub fn compare_caution(&self, other: &Self) -> Option<Ordering> {
+        //! `Greater` when this schedule `dominates` `other` and `Less` when it is the other way around
+        //!     `Equal` when both have the same waits, and None when each is more cautious somewhere
+        match (self.dominates(other), other.dominates(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+impl Display for RetrySimulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        //! one line, with every time in whole milliseconds since the first failure
+        //!     such as `attempt 1 @0ms, wait 100ms; attempt 2 @100ms, wait 200ms; give up @300ms`
+        //! when `SIMULATION_LIMIT` was reached first it ends with `no give up` instead
+        let first = self
+            .waits
+            .first()
+            .map(|(when, _)| *when)
+            .or(self.gave_up_at);
+        let since_first = |when: Instant| {
+            first.map_or(0, |first| when.saturating_duration_since(first).as_millis())
+        };
+        for (n, (when, wait)) in self.waits.iter().enumerate() {
+            write!(
+                f,
+                "attempt {} @{}ms, wait {}ms; ",
+                n + 1,
+                since_first(*when),
+                wait.as_millis()
+            )?;
+        }
+        match self.gave_up_at {
+            Some(when) => write!(f, "give up @{}ms", since_first(when)),
+            None => write!(f, "no give up"),
+        }
+    }
+}
+
+pub fn simulate<B>(policy: &B, failure_times: &[Instant]) -> RetrySimulation
+where
+    B: Backoff,
+{
+    //! asks `policy` about one recoverable failure after another until it gives up
+    //! the first failures happen at `failure_times`, which should be in order
+    //!     and once those run out every attempt fails the instant its wait is over
+    //!     so with no `failure_times` at all, the first failure is now and every attempt takes no time
+    //! failure times after the policy gave up are ignored
+    let mut history: Vec<((), Instant)> = Vec::new();
+    let mut waits = Vec::new();
+    let mut next_failure = failure_times.first().copied().unwrap_or_else(Instant::now);
+    let mut gave_up_at = None;
+    while history.len() < SIMULATION_LIMIT {
+        let failed_at = failure_times
+            .get(history.len())
+            .copied()
+            .unwrap_or(next_failure);
+        let Some(wait) = policy.next_delay(failed_at, &history) else {
+            gave_up_at = Some(failed_at);
+            break;
+        };
+        waits.push((failed_at, wait));
+        history.push(((), failed_at));
+        next_failure = failed_at.checked_add(wait).unwrap_or(failed_at);
+    }
+    let last_failure = gave_up_at.or(history.last().map(|(_, when)| *when));
+    let total_elapsed = match (history.first(), last_failure) {
+        (Some((_, first)), Some(last)) => last.saturating_duration_since(*first),
+        _ => Duration::ZERO,
+    };
+    RetrySimulation {
+        attempts: waits.len() + usize::from(gave_up_at.is_some()),
+        waits,
+        gave_up_at,
+        total_elapsed,
+    }
+}
+
+mod test {
+    #[test]
+    fn exponential_schedule_simulated() {
+        use super::simulate;
+        use crate::backoff::ExponentialBackoff;
+        use std::time::Duration;
+        let sim = simulate(&ExponentialBackoff::DEFAULT, &[]);
+        let waits: Vec<_> = sim.waits.iter().map(|(_, wait)| *wait).collect();
+        assert_eq!(
+            waits,
+            [100, 200, 400, 800].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(sim.attempts, 5);
+        assert_eq!(sim.total_wait(), Duration::from_millis(1500));
+        assert_eq!(sim.total_elapsed, Duration::from_millis(1500));
+        assert_eq!(
+            sim.gave_up_at,
+            sim.waits
+                .first()
+                .map(|(first, _)| *first + Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn schedule_displayed() {
+        use super::simulate;
+        use crate::backoff::ExponentialBackoff;
+        use std::time::Duration;
+        let policy = ExponentialBackoff {
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        };
+        assert_eq!(
+            simulate(&policy, &[]).to_string(),
+            "attempt 1 @0ms, wait 100ms; attempt 2 @100ms, wait 200ms; give up @300ms"
+        );
+        let no_retries = ExponentialBackoff {
+            max_attempts: 1,
+            ..ExponentialBackoff::DEFAULT
+        };
+        assert_eq!(simulate(&no_retries, &[]).to_string(), "give up @0ms");
+        let policy = ExponentialBackoff {
+            base: Duration::from_micros(1500),
+            max_attempts: 2,
+            ..ExponentialBackoff::DEFAULT
+        };
+        assert_eq!(
+            simulate(&policy, &[]).to_string(),
+            "attempt 1 @0ms, wait 1ms; give up @1ms"
+        );
+    }
+
+    #[test]
+    fn schedules_compared() {
+        use super::simulate;
+        use crate::backoff::ExponentialBackoff;
+        use std::cmp::Ordering;
+        use std::time::Duration;
+        let current = simulate(&ExponentialBackoff::DEFAULT, &[]);
+        let slower = simulate(
+            &ExponentialBackoff {
+                base: Duration::from_millis(200),
+                ..ExponentialBackoff::DEFAULT
+            },
+            &[],
+        );
+        let fewer = simulate(
+            &ExponentialBackoff {
+                max_attempts: 3,
+                ..ExponentialBackoff::DEFAULT
+            },
+            &[],
+        );
+        let flat = simulate(
+            &ExponentialBackoff {
+                base: Duration::from_millis(300),
+                multiplier: 1,
+                ..ExponentialBackoff::DEFAULT
+            },
+            &[],
+        );
+        assert!(slower.dominates(&current));
+        assert!(!current.dominates(&slower));
+        assert!(fewer.dominates(&current));
+        assert!(current.dominates(&current));
+        assert_eq!(slower.compare_caution(&current), Some(Ordering::Greater));
+        assert_eq!(current.compare_caution(&fewer), Some(Ordering::Less));
+        assert_eq!(current.compare_caution(&current), Some(Ordering::Equal));
+        assert_eq!(flat.compare_caution(&current), None);
+    }
+
+    #[test]
+    fn given_failure_times_are_used() {
+        use super::simulate;
+        use crate::backoff::BudgetedExponential;
+        use std::time::{Duration, Instant};
+        let start = Instant::now();
+        let policy = BudgetedExponential {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            total_budget: Duration::from_secs(1),
+        };
+        let slow_attempts = [0, 300, 800].map(|ms| start + Duration::from_millis(ms));
+        let sim = simulate(&policy, &slow_attempts);
+        assert_eq!(
+            sim.waits,
+            vec![
+                (slow_attempts[0], Duration::from_millis(100)),
+                (slow_attempts[1], Duration::from_millis(200)),
+            ]
+        );
+        assert_eq!(sim.gave_up_at, Some(slow_attempts[2]));
+        assert_eq!(sim.attempts, 3);
+        assert_eq!(sim.total_elapsed, Duration::from_millis(800));
+    }
+
+    #[test]
+    fn policy_that_never_gives_up_is_cut_off() {
+        use super::{simulate, SIMULATION_LIMIT};
+        use crate::backoff::Backoff;
+        use std::time::{Duration, Instant};
+        struct Forever;
+        impl Backoff for Forever {
+            fn next_delay<R>(&self, _: Instant, _: &[(R, Instant)]) -> Option<Duration> {
+                Some(Duration::from_secs(1))
+            }
+        }
+        let sim = simulate(&Forever, &[]);
+        assert_eq!(sim.gave_up_at, None);
+        assert_eq!(sim.attempts, SIMULATION_LIMIT);
+        assert_eq!(
+            sim.total_elapsed,
+            Duration::from_secs(u64::try_from(SIMULATION_LIMIT).unwrap() - 1)
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! for checking a `Retryable` implementation of one's own in a unit test
+//! its `wait_time` is asked about one failure after another, with the history built up as a driver would
+//!     but without any driver, runtime or sleeping
+//! each failure in the history is a clone of the error being checked
+//!     so a `wait_time` that looks at the errors in its history sees the same one every time
+
+use crate::retryable::Retryable;
+use std::time::{Duration, Instant};
+
+pub fn backoff_schedule<R>(error: &R, failure_times: &[Instant]) -> Vec<Option<Duration>>
+where
+    R: Retryable + Clone,
+{
+    //! what `wait_time` of `error` says after each failure in `failure_times`, in order
+    //!     with the failures before each one as its history
+    //! it stops at the first `None`, since no driver asks again after that
+    //!     so it is shorter than `failure_times` when the policy gave up before the end
+    let mut history: Vec<(R, Instant)> = Vec::with_capacity(failure_times.len());
+    let mut schedule = Vec::with_capacity(failure_times.len());
+    for when in failure_times {
+        let wait = error.wait_time(*when, &history);
+        schedule.push(wait);
+        if wait.is_none() {
+            break;
+        }
+        history.push((error.clone(), *when));
+    }
+    schedule
+}
+
+#[track_caller]
+pub fn assert_backoff_schedule<R>(
+    error: &R,
+    failure_times: &[Instant],
+    expected_waits: &[Option<Duration>],
+) where
+    R: Retryable + Clone,
+{
+    //! checks `backoff_schedule` of `error` against `expected_waits`
+    //!     which ends with a `None` where the policy should give up
+    //! # Panics
+    //! when they differ, saying which failure was the first to get the wrong wait
+    //!     and the location of the caller
+    let schedule = backoff_schedule(error, failure_times);
+    if let Some(n) = (0..schedule.len().max(expected_waits.len()))
+        .find(|n| schedule.get(*n) != expected_waits.get(*n))
+    {
+        panic!("wrong wait after failure {n}\nexpected {expected_waits:?}\n     got {schedule:?}");
+    }
+}
+
+mod test {
+    /// doubles the gap between its last two failures, and gives up once that reaches a second
+    #[allow(dead_code)]
+    #[derive(Debug, Clone)]
+    struct Doubling;
+
+    impl crate::retryable::Retryable for Doubling {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            let Some((_, last)) = previous_retriable_failures.last() else {
+                return Some(std::time::Duration::from_millis(100));
+            };
+            let doubled = my_time.saturating_duration_since(*last) * 2;
+            (doubled < std::time::Duration::from_secs(1)).then_some(doubled)
+        }
+    }
+
+    #[test]
+    fn schedule_checked() {
+        use super::{assert_backoff_schedule, backoff_schedule};
+        use std::time::{Duration, Instant};
+        let start = Instant::now();
+        let failures = [0, 100, 300, 700, 1500, 3100].map(|ms| start + Duration::from_millis(ms));
+        assert_backoff_schedule(
+            &Doubling,
+            &failures,
+            &[
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(800)),
+                None,
+            ],
+        );
+        assert_eq!(backoff_schedule(&Doubling, &failures[..2]).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "wrong wait after failure 1\nexpected [Some(100ms), None]\n     got [Some(100ms), Some(200ms), Some(400ms)]"
+    )]
+    fn off_by_one_caught() {
+        use super::assert_backoff_schedule;
+        use std::time::{Duration, Instant};
+        let start = Instant::now();
+        let failures = [0, 100, 300].map(|ms| start + Duration::from_millis(ms));
+        assert_backoff_schedule(
+            &Doubling,
+            &failures,
+            &[Some(Duration::from_millis(100)), None],
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! the guarantees the `backoff` policies make, checked over arbitrary configurations
+//!     never more than the configured maximum, never panicking on overflow
+//!     and for the exponential ones never shrinking until the cap is reached
+
+use proptest::prelude::*;
+use retryable_core::{next_backoff, Backoff, BudgetedExponential, ExponentialBackoff};
+use std::time::{Duration, Instant};
+
+fn any_duration() -> impl Strategy<Value = Duration> {
+    prop_oneof![
+        (0..10_000u64).prop_map(Duration::from_millis),
+        any::<u64>().prop_map(Duration::from_nanos),
+        any::<u64>().prop_map(Duration::from_secs),
+        Just(Duration::ZERO),
+        Just(Duration::MAX),
+    ]
+}
+
+fn any_exponential() -> impl Strategy<Value = ExponentialBackoff> {
+    (any_duration(), any::<u32>(), any_duration(), 0..64usize).prop_map(
+        |(base, multiplier, max_wait, max_attempts)| ExponentialBackoff {
+            base,
+            multiplier,
+            max_wait,
+            max_attempts,
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn exponential_never_exceeds_max_wait(config in any_exponential(), attempt in 0..128usize) {
+        if let Some(delay) = next_backoff(&config, attempt) {
+            prop_assert!(delay <= config.max_wait);
+        }
+    }
+
+    #[test]
+    fn exponential_gives_up_exactly_at_max_attempts(config in any_exponential(), attempt in 0..128usize) {
+        prop_assert_eq!(
+            next_backoff(&config, attempt).is_none(),
+            attempt + 1 >= config.max_attempts
+        );
+    }
+
+    #[test]
+    fn exponential_is_non_decreasing(config in any_exponential(), attempt in 0..126usize) {
+        prop_assume!(config.multiplier >= 1);
+        if let (Some(now), Some(next)) =
+            (next_backoff(&config, attempt), next_backoff(&config, attempt + 1))
+        {
+            prop_assert!(now <= next);
+        }
+    }
+
+    #[test]
+    fn budgeted_stays_within_budget(
+        base in any_duration(),
+        multiplier in any::<u32>(),
+        total_budget in any_duration(),
+        gaps in proptest::collection::vec(0..1_000u64, 0..16),
+    ) {
+        let policy = BudgetedExponential { base, multiplier, total_budget };
+        let start = Instant::now();
+        let mut history = Vec::new();
+        let mut at = start;
+        for gap in gaps {
+            history.push(((), at));
+            at += Duration::from_millis(gap);
+        }
+        if let Some(delay) = policy.next_delay(at, &history) {
+            let elapsed = at.saturating_duration_since(start);
+            prop_assert!(elapsed.checked_add(delay).is_some_and(|end| end <= total_budget));
+        }
+    }
+
+    #[test]
+    fn budgeted_is_non_decreasing(
+        base in any_duration(),
+        multiplier in 1..u32::MAX,
+        total_budget in any_duration(),
+        failures in 0..32usize,
+    ) {
+        let policy = BudgetedExponential { base, multiplier, total_budget };
+        let at = Instant::now();
+        let history = vec![((), at); failures];
+        let longer = vec![((), at); failures + 1];
+        if let (Some(now), Some(next)) =
+            (policy.next_delay(at, &history), policy.next_delay(at, &longer))
+        {
+            prop_assert!(now <= next);
+        }
+    }
+}
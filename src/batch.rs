@@ -0,0 +1,346 @@
+//! retrying every item of a batch, each one with its own history of failures
+//! the items go one after another
+//!     so that one item's waits never overlap another's attempts
+//! `repeatedly_try_batch` treats the batch as failed as soon as one item gives up
+//! `repeatedly_try_batch_partial` carries on and says which items never succeeded
+//! `retry_collect` and `retry_collect_each` are the same for a plain `Result` and a backoff policy
+//!     as `retry_if` is for `repeatedly_try`
+
+use crate::backoff::Backoff;
+use crate::retryable::{Retryable, RetryableResult};
+use crate::try_again::{repeatedly_try, retry_if};
+use std::{future::Future, time::Instant};
+
+/// what became of each item of a batch, in the order the items were given
+///     along with the argument each one was tried with so that the failed ones can be resubmitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchOutcome<ArgType, SuccessType, FatalErr> {
+    pub succeeded: Vec<(ArgType, SuccessType)>,
+    pub permanently_failed: Vec<(ArgType, FatalErr)>,
+}
+
+impl<ArgType, SuccessType, FatalErr> BatchOutcome<ArgType, SuccessType, FatalErr> {
+    pub fn is_complete(&self) -> bool {
+        //! no item failed, including when the batch was empty
+        self.permanently_failed.is_empty()
+    }
+}
+
+async fn try_each<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: &OneTryFun,
+    args: impl IntoIterator<Item = ArgType>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+    stop_at_first_failure: bool,
+) -> BatchOutcome<ArgType, SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    let (ctx, fatal_logger, recoverable_logger) = loggers;
+    let mut outcome = BatchOutcome {
+        succeeded: Vec::new(),
+        permanently_failed: Vec::new(),
+    };
+    for arg in args {
+        let result = repeatedly_try(
+            do_this_function,
+            arg.clone(),
+            (
+                &mut *ctx,
+                fatal_logger.as_ref(),
+                recoverable_logger.as_ref(),
+            ),
+        )
+        .await;
+        match result {
+            Ok(z) => outcome.succeeded.push((arg, z)),
+            Err(e) => {
+                outcome.permanently_failed.push((arg, e));
+                if stop_at_first_failure {
+                    break;
+                }
+            }
+        }
+    }
+    outcome
+}
+
+pub async fn repeatedly_try_batch<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    args: impl IntoIterator<Item = ArgType>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<Vec<SuccessType>, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! `repeatedly_try` on each of `args` in turn, giving the successes in the same order
+    //! the loggers see the errors of every item that gave up
+    //! # Errors
+    //! the fatal error of the first item that gave up, the items after it are not tried
+    let mut outcome = try_each(&do_this_function, args, loggers, true).await;
+    match outcome.permanently_failed.pop() {
+        Some((_, e)) => Err(e),
+        None => Ok(outcome.succeeded.into_iter().map(|(_, z)| z).collect()),
+    }
+}
+
+pub async fn repeatedly_try_batch_partial<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    args: impl IntoIterator<Item = ArgType>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> BatchOutcome<ArgType, SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try_batch` except that an item giving up does not stop the batch
+    //! every item is tried and the caller decides what a partial failure means
+    //!     such as by checking `is_complete`
+    try_each(&do_this_function, args, loggers, false).await
+}
+
+pub async fn retry_collect<
+    SuccessType,
+    ErrType,
+    ArgType,
+    OneTryFun,
+    Fut0,
+    ShouldRetry,
+    BackoffType,
+>(
+    do_this_function: OneTryFun,
+    args: impl IntoIterator<Item = ArgType>,
+    should_retry: ShouldRetry,
+    backoff: BackoffType,
+) -> Result<Vec<SuccessType>, ErrType>
+where
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, ErrType>>,
+    ShouldRetry: Fn(&ErrType) -> bool,
+    BackoffType: Backoff + Clone,
+{
+    //! `retry_if` on each of `args` in turn, giving the successes in the same order
+    //! each item starts over with its own clone of `backoff`
+    //! # Errors
+    //! the error of the first item that gave up, the items after it are not tried
+    let mut succeeded = Vec::new();
+    for arg in args {
+        succeeded.push(retry_if(&do_this_function, arg, &should_retry, backoff.clone()).await?);
+    }
+    Ok(succeeded)
+}
+
+pub async fn retry_collect_each<
+    SuccessType,
+    ErrType,
+    ArgType,
+    OneTryFun,
+    Fut0,
+    ShouldRetry,
+    BackoffType,
+>(
+    do_this_function: OneTryFun,
+    args: impl IntoIterator<Item = ArgType>,
+    should_retry: ShouldRetry,
+    backoff: BackoffType,
+) -> Vec<Result<SuccessType, ErrType>>
+where
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, ErrType>>,
+    ShouldRetry: Fn(&ErrType) -> bool,
+    BackoffType: Backoff + Clone,
+{
+    //! the same as `retry_collect` except that an item giving up does not stop the others
+    //!     so there is one result for every item, in the same order
+    let mut results = Vec::new();
+    for arg in args {
+        results.push(retry_if(&do_this_function, arg, &should_retry, backoff.clone()).await);
+    }
+    results
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Busy;
+
+    impl Retryable for Busy {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "still busy"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::ZERO)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn partial_failures_are_reported() {
+        use super::{repeatedly_try_batch, repeatedly_try_batch_partial};
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+
+        let attempts = Mutex::new(Vec::new());
+        let one_try = |item: u32| {
+            attempts.lock().unwrap().push(item);
+            async move {
+                match item {
+                    0 => RetryableResult::Fatal("zero"),
+                    odd if odd % 2 == 1 => RetryableResult::Retryable(Busy),
+                    even => RetryableResult::GoodResult(even * 10),
+                }
+            }
+        };
+        let mut gave_up = 0;
+        let outcome = repeatedly_try_batch_partial(
+            one_try,
+            [2, 3, 0, 4],
+            (
+                &mut gave_up,
+                Some(
+                    |_: &&'static str, _: std::time::Instant, gave_up: &mut u32| {
+                        *gave_up += 1;
+                    },
+                ),
+                None::<fn(&Busy, std::time::Instant, &mut u32)>,
+            ),
+        )
+        .await;
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.succeeded, vec![(2, 20), (4, 40)]);
+        assert_eq!(
+            outcome.permanently_failed,
+            vec![(3, "still busy"), (0, "zero")]
+        );
+        assert_eq!(gave_up, 2);
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 3, 3, 3, 0, 4]);
+
+        attempts.lock().unwrap().clear();
+        let strict = repeatedly_try_batch(
+            one_try,
+            [2, 3, 0, 4],
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Busy, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(strict, Err("still busy"));
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 3, 3, 3]);
+
+        let all_good = repeatedly_try_batch(
+            one_try,
+            [2, 4],
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Busy, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(all_good, Ok(vec![20, 40]));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn plain_results_collected() {
+        use super::{retry_collect, retry_collect_each};
+        use crate::backoff::ExponentialBackoff;
+        use std::sync::Mutex;
+        use std::time::Duration;
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        };
+        let attempts = Mutex::new(Vec::new());
+        let one_try = |item: u32| {
+            attempts.lock().unwrap().push(item);
+            async move {
+                match item {
+                    0 => Err("zero"),
+                    odd if odd % 2 == 1 => Err("busy"),
+                    even => Ok(even * 10),
+                }
+            }
+        };
+        let is_busy = |e: &&'static str| *e == "busy";
+
+        let each = retry_collect_each(one_try, [2, 3, 0, 4], is_busy, policy).await;
+        assert_eq!(each, vec![Ok(20), Err("busy"), Err("zero"), Ok(40)]);
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 3, 3, 3, 0, 4]);
+
+        attempts.lock().unwrap().clear();
+        let strict = retry_collect(one_try, [2, 0, 4], is_busy, policy).await;
+        assert_eq!(strict, Err("zero"));
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 0]);
+
+        let all_good = retry_collect(one_try, [2, 4], is_busy, policy).await;
+        assert_eq!(all_good, Ok(vec![20, 40]));
+    }
+}
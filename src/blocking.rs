@@ -0,0 +1,763 @@
+//! the same retry loop as `repeatedly_try` but for synchronous functions
+//!     sleeping the current thread between attempts instead of awaiting
+//! there is also a cancellable version for worker threads that must shut down promptly
+//!     and one that gives each attempt its own thread so an attempt that hangs can be given up on
+//! `repeatedly_try_spawn_blocking` runs the whole loop on tokio's blocking thread pool
+//!     for a synchronous retry body, such as a blocking database driver, inside an async service
+
+use crate::retryable::{Retryable, RetryableResult, WaitDecision};
+use crate::sleep::{plausible, LoopClock};
+use crate::try_again::log_and_give_up;
+use crossbeam_channel::RecvTimeoutError as AttemptTimeoutError;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+pub trait CancelSignal {
+    //! something that can say the whole retry loop should stop
+    //! the waiting is done by the signal itself so that it can wake up early when cancelled
+
+    fn is_cancelled(&self) -> bool;
+
+    /// sleep for up to `how_long`, true if cancelled before or during the sleep
+    fn sleep_unless_cancelled(&self, how_long: Duration) -> bool;
+}
+
+impl CancelSignal for Receiver<()> {
+    fn is_cancelled(&self) -> bool {
+        self.try_recv().is_ok()
+    }
+
+    fn sleep_unless_cancelled(&self, how_long: Duration) -> bool {
+        //! a sent `()` cancels
+        //! if every sender has been dropped, nothing can cancel any more so this just sleeps
+        let started = Instant::now();
+        match self.recv_timeout(how_long) {
+            Ok(()) => true,
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(how_long.saturating_sub(started.elapsed()));
+                false
+            }
+        }
+    }
+}
+
+impl CancelSignal for Arc<AtomicBool> {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Acquire)
+    }
+
+    fn sleep_unless_cancelled(&self, how_long: Duration) -> bool {
+        //! setting the flag to true cancels
+        //! the flag is checked every `ATOMIC_POLL_INTERVAL` during the sleep
+        let deadline = Instant::now() + how_long;
+        loop {
+            if self.is_cancelled() {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            std::thread::sleep(remaining.min(ATOMIC_POLL_INTERVAL));
+        }
+    }
+}
+
+pub const ATOMIC_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// how many timed out attempts of one `retry_blocking_with_timeout` may still be running
+///     before it gives up instead of starting yet another thread
+pub const MAX_HUNG_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelledOr<F> {
+    /// the cancel signal fired before the loop reached an answer
+    Cancelled,
+    /// the loop gave up on its own like it does without cancellation
+    Fatal(F),
+}
+
+pub fn repeatedly_try_blocking<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try` for a synchronous `do_this_function`
+    //!     the current thread sleeps between attempts
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    repeatedly_try_blocking_inner(do_this_function, arg, None::<&Receiver<()>>, loggers).map_err(
+        |e| match e {
+            CancelledOr::Fatal(f) => f,
+            CancelledOr::Cancelled => unreachable!("there is no cancel signal to fire"),
+        },
+    )
+}
+
+pub fn repeatedly_try_blocking_cancellable<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    Cancel,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    cancel: &Cancel,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, CancelledOr<FatalErr>>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    Cancel: CancelSignal,
+{
+    //! `repeatedly_try_blocking` that also stops as soon as `cancel` fires
+    //!     it is checked before each wait and while waiting
+    //!     an attempt that is already running is not interrupted
+    //! being cancelled is not a fatal error so the loggers are not called for it
+    //! # Errors
+    //! `Cancelled` when `cancel` fired
+    //! otherwise `Fatal` when there are too many recoverable errors or one of the steps gave a `FatalErr` directly
+    repeatedly_try_blocking_inner(do_this_function, arg, Some(cancel), loggers)
+}
+
+pub fn retry_blocking_with_timeout<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    TimeoutFun,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    per_attempt: Duration,
+    on_timeout: TimeoutFun,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    SuccessType: Send + 'static,
+    RecoverableErr: Retryable<FatalError = FatalErr> + Send + 'static,
+    FatalErr: Send + 'static,
+    ArgType: Sized + Clone + Send + 'static,
+    OneTryFun: Fn(ArgType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr>
+        + Send
+        + Sync
+        + 'static,
+    TimeoutFun: Fn(Duration) -> RecoverableErr,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! `repeatedly_try_blocking` with each attempt run on a thread of its own
+    //!     and given up on after `per_attempt`, as the recoverable error `on_timeout` makes from `per_attempt`
+    //! a thread can not be killed, so an attempt that hangs keeps its thread
+    //!     until it finishes on its own, and whatever it gives is thrown away
+    //!     so every attempt that times out can leave one more thread behind
+    //! once `MAX_HUNG_ATTEMPTS` of those are still running, the loop gives up
+    //!     with the fatal error of the `on_timeout` error rather than start another
+    //! a panic in an attempt is passed on to the caller
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    //!     or too many timed out attempts are still running
+    let do_this_function = Arc::new(do_this_function);
+    let running = Arc::new(AtomicUsize::new(0));
+    let one_try = |arg: ArgType| {
+        if running.load(Ordering::Acquire) >= MAX_HUNG_ATTEMPTS {
+            return RetryableResult::Fatal(on_timeout(per_attempt).to_fatal());
+        }
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let do_this_function = Arc::clone(&do_this_function);
+        let still_running = StillRunning::start(Arc::clone(&running));
+        let attempt = std::thread::spawn(move || {
+            let outcome = do_this_function(arg);
+            drop(still_running);
+            // the receiver is gone if this attempt already timed out
+            let _ = sender.send(outcome);
+        });
+        match receiver.recv_timeout(per_attempt) {
+            Ok(outcome) => outcome,
+            Err(AttemptTimeoutError::Timeout) => {
+                RetryableResult::Retryable(on_timeout(per_attempt))
+            }
+            Err(AttemptTimeoutError::Disconnected) => match attempt.join() {
+                Err(panicked) => std::panic::resume_unwind(panicked),
+                Ok(()) => unreachable!("the attempt sends before its thread finishes"),
+            },
+        }
+    };
+    repeatedly_try_blocking(one_try, arg, loggers)
+}
+
+/// counts an attempt thread of `retry_blocking_with_timeout` until it finishes or panics
+struct StillRunning(Arc<AtomicUsize>);
+
+impl StillRunning {
+    fn start(running: Arc<AtomicUsize>) -> Self {
+        running.fetch_add(1, Ordering::AcqRel);
+        Self(running)
+    }
+}
+
+impl Drop for StillRunning {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// sets the flag once the future waiting on the blocking loop is dropped
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+pub async fn repeatedly_try_spawn_blocking<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    loggers: (
+        FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> (Result<SuccessType, FatalErr>, FailLogContext)
+where
+    SuccessType: Send + 'static,
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    FatalErr: Send + 'static,
+    ArgType: Sized + Clone + Send + 'static,
+    OneTryFun:
+        Fn(ArgType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr> + Send + 'static,
+    FailLogContext: Send + 'static,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext) + Send + 'static,
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext) + Send + 'static,
+{
+    //! `repeatedly_try_blocking` on a thread of tokio's blocking pool, so the async runtime is not blocked
+    //!     by the attempts or the waits between them
+    //! the log context is moved to that thread and given back along with the result
+    //! dropping the returned future, such as when the task awaiting it is aborted
+    //!     cancels the loop as `repeatedly_try_blocking_cancellable` would
+    //!     so it stops at its next wait, or during it, instead of retrying for nobody
+    //!     but an attempt that is already running is not interrupted
+    //! a panic in an attempt is passed on to the caller
+    //! # Panics
+    //! when called outside of a tokio runtime
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let _cancel_on_drop = CancelOnDrop(Arc::clone(&cancelled));
+    let (mut ctx, fatal_logger, recoverable_logger) = loggers;
+    let finished = tokio::task::spawn_blocking(move || {
+        let z = repeatedly_try_blocking_cancellable(
+            do_this_function,
+            arg,
+            &cancelled,
+            (&mut ctx, fatal_logger, recoverable_logger),
+        );
+        (z, ctx)
+    })
+    .await;
+    match finished {
+        Ok((z, ctx)) => (
+            z.map_err(|e| match e {
+                CancelledOr::Fatal(f) => f,
+                CancelledOr::Cancelled => {
+                    unreachable!("only cancelled once nothing is waiting for the result")
+                }
+            }),
+            ctx,
+        ),
+        Err(join_error) => match join_error.try_into_panic() {
+            Ok(panicked) => std::panic::resume_unwind(panicked),
+            Err(_) => panic!("the runtime shut down before the blocking retries finished"),
+        },
+    }
+}
+
+fn repeatedly_try_blocking_inner<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    Cancel,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    cancel: Option<&Cancel>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, CancelledOr<FatalErr>>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    Cancel: CancelSignal,
+{
+    let mut my_retriable_failures = Vec::<(RecoverableErr, Instant)>::new();
+    let mut my_attempt_durations = Vec::<Duration>::new();
+    let mut clock = LoopClock::default();
+    loop {
+        let attempt_start = Instant::now();
+        match do_this_function(arg.clone()) {
+            RetryableResult::GoodResult(z) => {
+                return Ok(z);
+            }
+            RetryableResult::Retryable(r) => {
+                let this_attempt_took = attempt_start.elapsed();
+                let this_time = clock.now();
+                if r.resets_history() {
+                    my_retriable_failures.clear();
+                    my_attempt_durations.clear();
+                }
+                let how_long_to_wait = match r.timed_wait_decision(
+                    this_time,
+                    this_attempt_took,
+                    &my_retriable_failures,
+                    &my_attempt_durations,
+                ) {
+                    WaitDecision::Wait(how_long_to_wait) => plausible(how_long_to_wait),
+                    WaitDecision::Immediate => Duration::ZERO,
+                    WaitDecision::GiveUp => {
+                        let f = r.escalate(&my_retriable_failures);
+                        return Err(CancelledOr::Fatal(log_and_give_up(
+                            loggers,
+                            &my_retriable_failures,
+                            f,
+                            this_time,
+                        )));
+                    }
+                };
+                my_retriable_failures.push((r, this_time));
+                my_attempt_durations.push(this_attempt_took);
+                let skipped = clock.skip(how_long_to_wait);
+                match cancel {
+                    Some(cancel) => {
+                        if cancel.is_cancelled()
+                            || (!skipped && cancel.sleep_unless_cancelled(how_long_to_wait))
+                        {
+                            return Err(CancelledOr::Cancelled);
+                        }
+                    }
+                    None if skipped => {}
+                    None => std::thread::sleep(how_long_to_wait),
+                }
+            }
+            RetryableResult::Fatal(f) => {
+                let this_time = clock.now();
+                return Err(CancelledOr::Fatal(log_and_give_up(
+                    loggers,
+                    &my_retriable_failures,
+                    f,
+                    this_time,
+                )));
+            }
+        }
+    }
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    struct Busy;
+
+    impl Retryable for Busy {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "still busy"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_secs(60))
+        }
+    }
+
+    #[allow(dead_code)]
+    type NoLoggers = (
+        Option<fn(&&'static str, std::time::Instant, &mut ())>,
+        Option<fn(&Busy, std::time::Instant, &mut ())>,
+    );
+
+    #[allow(dead_code)]
+    struct QuickBusy;
+
+    impl Retryable for QuickBusy {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "still busy"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            Some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[test]
+    fn blocking_retries_until_success() {
+        use super::repeatedly_try_blocking;
+        use crate::retryable::RetryableResult;
+        use std::cell::Cell;
+        let attempts = Cell::new(0);
+        let one_try = |x: u8| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                RetryableResult::Retryable(QuickBusy)
+            } else {
+                RetryableResult::GoodResult(x)
+            }
+        };
+        let z = repeatedly_try_blocking(
+            one_try,
+            5,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&QuickBusy, std::time::Instant, &mut ())>,
+            ),
+        );
+        assert_eq!(z, Ok(5));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[test]
+    fn cancelled_during_sleep() {
+        use super::{repeatedly_try_blocking_cancellable, CancelledOr};
+        use crate::retryable::RetryableResult;
+        use std::sync::{atomic::AtomicBool, mpsc, Arc};
+        use std::time::{Duration, Instant};
+
+        let (tx, rx) = mpsc::channel();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(()).unwrap();
+        });
+        let started = Instant::now();
+        let (fatal, recoverable): NoLoggers = (None, None);
+        let z = repeatedly_try_blocking_cancellable(
+            |()| RetryableResult::<(), _, _>::Retryable(Busy),
+            (),
+            &rx,
+            (&mut (), fatal, recoverable),
+        );
+        assert_eq!(z, Err(CancelledOr::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        canceller.join().unwrap();
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let setter = {
+            let flag = flag.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(20));
+                flag.store(true, std::sync::atomic::Ordering::Release);
+            })
+        };
+        let started = Instant::now();
+        let z = repeatedly_try_blocking_cancellable(
+            |()| RetryableResult::<(), _, _>::Retryable(Busy),
+            (),
+            &flag,
+            (&mut (), fatal, recoverable),
+        );
+        assert_eq!(z, Err(CancelledOr::Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        setter.join().unwrap();
+
+        let z = repeatedly_try_blocking_cancellable(
+            |()| RetryableResult::<(), Busy, _>::Fatal("broken"),
+            (),
+            &flag,
+            (&mut (), fatal, recoverable),
+        );
+        assert_eq!(z, Err(CancelledOr::Fatal("broken")));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    enum Hung {
+        TimedOut(std::time::Duration),
+    }
+
+    impl Retryable for Hung {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "hung every time"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn hung_attempts_time_out() {
+        use super::retry_blocking_with_timeout;
+        use crate::retryable::RetryableResult;
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use std::time::{Duration, Instant};
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        let hangs_first = move |x: u8| {
+            if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                std::thread::sleep(Duration::from_secs(2));
+            }
+            RetryableResult::<_, Hung, _>::GoodResult(x)
+        };
+        let started = Instant::now();
+        let no_loggers = (
+            None::<fn(&&'static str, Instant, &mut ())>,
+            None::<fn(&Hung, Instant, &mut ())>,
+        );
+        let z = retry_blocking_with_timeout(
+            hangs_first,
+            9,
+            Duration::from_millis(20),
+            Hung::TimedOut,
+            (&mut (), no_loggers.0, no_loggers.1),
+        );
+        assert_eq!(z, Ok(9));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let z = retry_blocking_with_timeout(
+            |()| {
+                std::thread::sleep(Duration::from_millis(200));
+                RetryableResult::<(), Hung, _>::Fatal("too late to matter")
+            },
+            (),
+            Duration::from_millis(5),
+            Hung::TimedOut,
+            (&mut (), no_loggers.0, no_loggers.1),
+        );
+        assert_eq!(z, Err("hung every time"));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct NeverFinishes(std::time::Duration);
+
+    impl Retryable for NeverFinishes {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "too many hung attempts"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            Some(std::time::Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn hung_threads_are_capped() {
+        use super::{retry_blocking_with_timeout, MAX_HUNG_ATTEMPTS};
+        use crate::retryable::RetryableResult;
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use std::time::{Duration, Instant};
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        let z = retry_blocking_with_timeout(
+            move |()| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_secs(2));
+                RetryableResult::<(), NeverFinishes, _>::GoodResult(())
+            },
+            (),
+            Duration::from_millis(5),
+            NeverFinishes,
+            (
+                &mut (),
+                None::<fn(&&'static str, Instant, &mut ())>,
+                None::<fn(&NeverFinishes, Instant, &mut ())>,
+            ),
+        );
+        assert_eq!(z, Err("too many hung attempts"));
+        // the last of the hung threads may not have got to counting itself yet
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(attempts.load(Ordering::SeqCst), MAX_HUNG_ATTEMPTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "attempt blew up")]
+    fn attempt_panics_reach_caller() {
+        use super::retry_blocking_with_timeout;
+        use crate::retryable::RetryableResult;
+        let _ = retry_blocking_with_timeout(
+            |()| -> RetryableResult<(), Hung, &'static str> { panic!("attempt blew up") },
+            (),
+            std::time::Duration::from_secs(5),
+            Hung::TimedOut,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Hung, std::time::Instant, &mut ())>,
+            ),
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn blocking_loop_off_the_runtime() {
+        use super::repeatedly_try_spawn_blocking;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let (z, replayed) = repeatedly_try_spawn_blocking(
+            move |x: u8| {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    RetryableResult::Retryable(QuickBusy)
+                } else {
+                    RetryableResult::GoodResult(x)
+                }
+            },
+            5,
+            (
+                0,
+                None::<fn(&&'static str, std::time::Instant, &mut usize)>,
+                Some(|_: &QuickBusy, _: std::time::Instant, replayed: &mut usize| *replayed += 1),
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(5));
+        assert_eq!(replayed, 0);
+
+        let (z, replayed) = repeatedly_try_spawn_blocking(
+            |()| RetryableResult::<(), _, _>::Fatal("broken"),
+            (),
+            (
+                Vec::new(),
+                Some(
+                    |f: &&'static str, _: std::time::Instant, seen: &mut Vec<&'static str>| {
+                        seen.push(*f);
+                    },
+                ),
+                None::<fn(&QuickBusy, std::time::Instant, &mut Vec<&'static str>)>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("broken"));
+        assert_eq!(replayed, vec!["broken"]);
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn dropped_future_cancels_blocking_loop() {
+        use super::repeatedly_try_spawn_blocking;
+        use crate::retryable::RetryableResult;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        let held_by_loop = Arc::new(());
+        let in_loop = Arc::clone(&held_by_loop);
+        let (fatal, recoverable): NoLoggers = (None, None);
+        let given_up_on = async_std::future::timeout(
+            Duration::from_millis(20),
+            repeatedly_try_spawn_blocking(
+                move |()| {
+                    let _ = &in_loop;
+                    RetryableResult::<(), _, _>::Retryable(Busy)
+                },
+                (),
+                ((), fatal, recoverable),
+            ),
+        )
+        .await;
+        assert!(given_up_on.is_err());
+        let started = Instant::now();
+        while Arc::strong_count(&held_by_loop) > 1 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "the loop kept waiting out its 60s backoff"
+            );
+            async_std::task::sleep(Duration::from_millis(5)).await;
+        }
+    }
+}
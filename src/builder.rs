@@ -0,0 +1,2565 @@
+//! the knobs on the retry loop that most callers do not need
+//! `repeatedly_try` is the same as running a `RetryBuilder` with nothing set
+//! every hook is optional and is boxed so that adding more of them
+//!     does not add more type parameters for every caller
+//! the boxes are `Arc`s so that one configured builder can be cloned for each call site
+
+use crate::backoff::FailureWindow;
+use crate::gate::RetryGate;
+use crate::give_up::{GaveUp, GiveUpReason};
+use crate::metrics;
+use crate::retryable::{Retryable, RetryableResult, WaitDecision};
+use crate::sleep::{GlobalSleeper, LoopClock, Sleeper, IMPLAUSIBLE_WAIT};
+use crate::try_again::{as_attempt, current_attempt, log_and_give_up};
+use std::{
+    fmt::Debug,
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+type PromoteFatal<'a, R, F> = Arc<dyn Fn(&F) -> Option<R> + Send + Sync + 'a>;
+type HealthCheck<'a> = Arc<dyn Fn() -> bool + Send + Sync + 'a>;
+type RetryWindow<'a> = Arc<dyn Fn(Instant) -> bool + Send + Sync + 'a>;
+type OnWait<'a> = Arc<dyn Fn(&WaitInfo) + Send + Sync + 'a>;
+type OnTimeout<'a, R> = Arc<dyn Fn(Duration) -> R + Send + Sync + 'a>;
+type OnGiveUp<'a, R, F> = Arc<
+    dyn Fn(usize, Duration, Option<&R>, &F) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>
+        + Send
+        + Sync
+        + 'a,
+>;
+type Permit<'a> = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> + Send + Sync + 'a>;
+type ShouldRetry<'a, R> =
+    Arc<dyn Fn(&R) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> + Send + Sync + 'a>;
+
+/// what the loop is about to do after a recoverable error, for the `on_wait` hook
+/// `wanted` is what the policy asked for and `waiting` is what it will actually wait
+///     they only differ when `max_wait` or `implausible_wait` clamped the wait, and then `truncated` is true
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitInfo {
+    pub attempt: usize,
+    pub wanted: Duration,
+    pub waiting: Duration,
+    pub truncated: bool,
+}
+
+impl WaitInfo {
+    #[must_use]
+    pub fn truncated_by(&self) -> Duration {
+        //! how much shorter the wait is than what the policy wanted
+        self.wanted.saturating_sub(self.waiting)
+    }
+}
+
+/// how one attempt of a traced retry loop went
+/// errors are kept as their `Debug` text like in a `RetryEvent`
+///     so a trace can be compared in a test without the error types being `PartialEq`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceOutcome {
+    Good,
+    /// `decision` is what the loop actually did, after `max_wait` and the other hooks
+    ///     so it is `GiveUp` whenever this was the last attempt
+    Recoverable {
+        error: String,
+        decision: WaitDecision,
+    },
+    Fatal {
+        error: String,
+    },
+}
+
+/// what an attempt run by `run_with_context` knows about the budget of the loop around it
+///     so it can adapt, such as skipping an optional expensive step when little time is left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryContext {
+    attempt: usize,
+    max_attempts: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl RetryContext {
+    #[must_use]
+    pub fn attempt(&self) -> usize {
+        //! 1 for the first attempt
+        self.attempt
+    }
+
+    #[must_use]
+    pub fn attempts_remaining(&self) -> Option<usize> {
+        //! how many more attempts there can be after this one
+        //!     counting only the `max_attempts` of the builder, the policy may give up sooner
+        //! None when the builder has no `max_attempts`
+        self.max_attempts
+            .map(|max_attempts| max_attempts.saturating_sub(self.attempt))
+    }
+
+    #[must_use]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    #[must_use]
+    pub fn time_remaining(&self) -> Option<Duration> {
+        //! how long until the deadline of the builder, zero once it has passed
+        //! None when the builder has neither a `deadline` nor a `total_timeout`
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// how a loop run with `run_map_final_error` went, for enriching the error it gave up with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryStats {
+    pub attempts: usize,
+    /// from the start of the first attempt until the loop gave up
+    pub elapsed: Duration,
+    pub reason: GiveUpReason,
+}
+
+/// one attempt of a loop run with `run_traced`, in the order they happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub attempt: usize,
+    pub started: Instant,
+    pub outcome: TraceOutcome,
+}
+
+/// where the loop reports each attempt
+///     `()` for the untraced loops so they do not pay for formatting errors
+trait Tracer<R, F> {
+    fn good(&mut self, attempt: usize, started: Instant);
+    fn recoverable(&mut self, attempt: usize, started: Instant, error: &R, decision: WaitDecision);
+    fn fatal(&mut self, attempt: usize, started: Instant, error: &F);
+    /// the last recoverable error was going to be retried, but the loop gave up while waiting
+    fn gave_up_waiting(&mut self);
+}
+
+impl<R, F> Tracer<R, F> for () {
+    fn good(&mut self, _: usize, _: Instant) {}
+    fn recoverable(&mut self, _: usize, _: Instant, _: &R, _: WaitDecision) {}
+    fn fatal(&mut self, _: usize, _: Instant, _: &F) {}
+    fn gave_up_waiting(&mut self) {}
+}
+
+impl<R, F> Tracer<R, F> for Option<&mut Vec<TraceEvent>>
+where
+    R: Debug,
+    F: Debug,
+{
+    fn good(&mut self, attempt: usize, started: Instant) {
+        if let Some(trace) = self {
+            trace.push(TraceEvent {
+                attempt,
+                started,
+                outcome: TraceOutcome::Good,
+            });
+        }
+    }
+
+    fn recoverable(&mut self, attempt: usize, started: Instant, error: &R, decision: WaitDecision) {
+        if let Some(trace) = self {
+            trace.push(TraceEvent {
+                attempt,
+                started,
+                outcome: TraceOutcome::Recoverable {
+                    error: format!("{error:?}"),
+                    decision,
+                },
+            });
+        }
+    }
+
+    fn fatal(&mut self, attempt: usize, started: Instant, error: &F) {
+        if let Some(trace) = self {
+            trace.push(TraceEvent {
+                attempt,
+                started,
+                outcome: TraceOutcome::Fatal {
+                    error: format!("{error:?}"),
+                },
+            });
+        }
+    }
+
+    fn gave_up_waiting(&mut self) {
+        if let Some(TraceEvent {
+            outcome: TraceOutcome::Recoverable { decision, .. },
+            ..
+        }) = self.as_mut().and_then(|trace| trace.last_mut())
+        {
+            *decision = WaitDecision::GiveUp;
+        }
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub struct RetryBuilder<'a, R, F, S = GlobalSleeper>
+where
+    R: Retryable<FatalError = F>,
+{
+    promote_fatal: Option<PromoteFatal<'a, R, F>>,
+    health: Option<HealthCheck<'a>>,
+    should_retry: Option<ShouldRetry<'a, R>>,
+    deadline: Option<Instant>,
+    total_timeout: Option<Duration>,
+    max_attempts: Option<usize>,
+    history_limit: Option<usize>,
+    strict: bool,
+    max_wait: Option<Duration>,
+    implausible_wait: Duration,
+    on_wait: Option<OnWait<'a>>,
+    on_give_up: Option<OnGiveUp<'a, R, F>>,
+    attempt_timeout: Option<(Duration, OnTimeout<'a, R>)>,
+    granularity: Option<Duration>,
+    settle: Option<Duration>,
+    gate: Option<&'a RetryGate>,
+    window: Option<(RetryWindow<'a>, Option<Duration>)>,
+    permit: Option<Permit<'a>>,
+    failure_window: Option<Arc<FailureWindow>>,
+    sleeper: S,
+}
+
+impl<R, F, S> Clone for RetryBuilder<'_, R, F, S>
+where
+    R: Retryable<FatalError = F>,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            promote_fatal: self.promote_fatal.clone(),
+            health: self.health.clone(),
+            should_retry: self.should_retry.clone(),
+            deadline: self.deadline,
+            total_timeout: self.total_timeout,
+            max_attempts: self.max_attempts,
+            history_limit: self.history_limit,
+            strict: self.strict,
+            max_wait: self.max_wait,
+            implausible_wait: self.implausible_wait,
+            on_wait: self.on_wait.clone(),
+            on_give_up: self.on_give_up.clone(),
+            attempt_timeout: self.attempt_timeout.clone(),
+            granularity: self.granularity,
+            settle: self.settle,
+            gate: self.gate,
+            window: self.window.clone(),
+            permit: self.permit.clone(),
+            failure_window: self.failure_window.clone(),
+            sleeper: self.sleeper.clone(),
+        }
+    }
+}
+
+impl<R, F, S> Default for RetryBuilder<'_, R, F, S>
+where
+    R: Retryable<FatalError = F>,
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            promote_fatal: None,
+            health: None,
+            should_retry: None,
+            deadline: None,
+            total_timeout: None,
+            max_attempts: None,
+            history_limit: None,
+            strict: false,
+            max_wait: None,
+            implausible_wait: IMPLAUSIBLE_WAIT,
+            on_wait: None,
+            on_give_up: None,
+            attempt_timeout: None,
+            granularity: None,
+            settle: None,
+            gate: None,
+            window: None,
+            permit: None,
+            failure_window: None,
+            sleeper: S::default(),
+        }
+    }
+}
+
+impl<R, F> RetryBuilder<'_, R, F>
+where
+    R: Retryable<FatalError = F>,
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<'a, R, F, S> RetryBuilder<'a, R, F, S>
+where
+    R: Retryable<FatalError = F>,
+{
+    #[must_use]
+    pub fn sleeper<S2>(self, sleeper: S2) -> RetryBuilder<'a, R, F, S2>
+    where
+        S2: Sleeper,
+    {
+        //! wait between attempts with `sleeper` instead of the global sleeper
+        //!     such as a `WasmSleeper` in the browser
+        RetryBuilder {
+            promote_fatal: self.promote_fatal,
+            health: self.health,
+            should_retry: self.should_retry,
+            deadline: self.deadline,
+            total_timeout: self.total_timeout,
+            max_attempts: self.max_attempts,
+            history_limit: self.history_limit,
+            strict: self.strict,
+            max_wait: self.max_wait,
+            implausible_wait: self.implausible_wait,
+            on_wait: self.on_wait,
+            on_give_up: self.on_give_up,
+            attempt_timeout: self.attempt_timeout,
+            granularity: self.granularity,
+            settle: self.settle,
+            gate: self.gate,
+            window: self.window,
+            permit: self.permit,
+            failure_window: self.failure_window,
+            sleeper,
+        }
+    }
+
+    #[must_use]
+    pub fn promote_fatal<P>(mut self, promote: P) -> Self
+    where
+        P: Fn(&F) -> Option<R> + Send + Sync + 'a,
+    {
+        //! a runtime knob for retrying on errors that are normally fatal
+        //!     such as a 500 that is usually a bug but is currently transient
+        //! when `promote` gives `Some` the loop treats the fatal error as that recoverable error
+        //! otherwise it stays fatal as usual
+        self.promote_fatal = Some(Arc::new(promote));
+        self
+    }
+
+    #[must_use]
+    pub fn health_check<H>(mut self, healthy: H) -> Self
+    where
+        H: Fn() -> bool + Send + Sync + 'a,
+    {
+        //! consulted before each retry, such as a flag kept up to date by a background health checker
+        //! when the dependency is unhealthy the loop gives up right away
+        //!     instead of spending the remaining attempts on it
+        self.health = Some(Arc::new(healthy));
+        self
+    }
+
+    #[must_use]
+    pub fn should_retry_async<P, Fut>(mut self, should_retry: P) -> Self
+    where
+        P: Fn(&R) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = bool> + Send + 'a,
+    {
+        //! an async check on every recoverable error, such as asking a feature flag service
+        //!     which overrides the static classification of that error
+        //! when it resolves to false the error is converted to fatal right away
+        //! the returned future can not borrow the error
+        //!     so take whatever is needed from it before the `async` block
+        self.should_retry = Some(Arc::new(move |r| Box::pin(should_retry(r))));
+        self
+    }
+
+    #[must_use]
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        //! give up instead of waiting when the next attempt would start after `deadline`
+        //! an attempt that is already running is not cut short unless `attempt_timeout` is set
+        self.deadline = Some(deadline);
+        self
+    }
+
+    #[must_use]
+    pub fn total_timeout(mut self, total_timeout: Duration) -> Self {
+        //! `deadline` but measured from when `run` or `run_detailed` gets called
+        //!     so one builder can be reused for many calls that each get the full `total_timeout`
+        //! with both this and `deadline` set, whichever comes first wins
+        self.total_timeout = Some(total_timeout);
+        self
+    }
+
+    #[must_use]
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        //! give up once `max_attempts` attempts have all failed recoverably
+        //!     whatever the policy of the recoverable error says, which can still give up sooner
+        //! the reason is `GiveUpReason::AttemptsExhausted`
+        //!     so it can be told apart from `PolicyGaveUp` when the policy runs out first
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    #[must_use]
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        //! keep only the last `limit` recoverable errors, and at least one
+        //!     so a loop that retries for a very long time does not hold on to every error
+        //! `wait_decision` only ever sees those, as do the loggers and `run_with_history`
+        //!     so a policy that counts or spaces out every failure since the start
+        //!     such as `ExponentialBackoff` with its `max_attempts`, should not be given a limit
+        //!     and `max_attempts` on this builder is the way to cap such a loop instead
+        //! the attempts in a `GaveUp` and given to `on_give_up` still count the forgotten ones
+        self.history_limit = Some(limit.max(1));
+        self
+    }
+
+    #[must_use]
+    pub fn strict(mut self) -> Self {
+        //! tell apart a policy that gave up on the very first recoverable error
+        //!     by giving `GiveUpReason::NoRetryAllowed` instead of `PolicyGaveUp` from `run_detailed`
+        //! that is correct behaviour for some errors but is more often a misconfigured policy
+        //!     so this is meant for tests and for checking a new policy
+        self.strict = true;
+        self
+    }
+
+    #[must_use]
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        //! never wait more than `max_wait` between attempts, whatever the policy asked for
+        //! the deadline is checked against the clamped wait
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    #[must_use]
+    pub fn implausible_wait(mut self, threshold: Duration) -> Self {
+        //! a wait longer than `threshold` is taken to be a bug in the policy
+        //!     such as `Duration::MAX` from an overflow, which would never end
+        //!     and is cut down to `threshold`, which `on_wait` sees as `truncated`
+        //! defaults to `IMPLAUSIBLE_WAIT`, unlike `max_wait` this is not meant to shape the policy
+        //! with a `deadline` or `total_timeout` as well, a wait that would still end past it
+        //!     gives up right away with `GiveUpReason::DeadlineExceeded` as any other wait would
+        self.implausible_wait = threshold;
+        self
+    }
+
+    #[must_use]
+    pub fn on_wait<W>(mut self, on_wait: W) -> Self
+    where
+        W: Fn(&WaitInfo) + Send + Sync + 'a,
+    {
+        //! called after each recoverable error that is going to be retried, before the wait
+        //!     so metrics can show when `max_wait` overrode the policy
+        self.on_wait = Some(Arc::new(on_wait));
+        self
+    }
+
+    #[must_use]
+    pub fn on_give_up<G>(mut self, on_give_up: G) -> Self
+    where
+        G: Fn(usize, Duration, Option<&R>, &F) + Send + Sync + 'a,
+    {
+        //! called once when the loop gives up, whatever the reason, before the loggers
+        //!     with how many attempts were made, how long the whole loop took
+        //!     the last recoverable error that was retried, None when the first attempt ended it
+        //!     and the fatal error the loop is giving back
+        //! for one summary line such as `gave up after 5 attempts over 3.1s`
+        //!     where replaying every recoverable error through a logger is too noisy
+        //! the loggers are still called as well, so leave them out to only have the summary
+        self.on_give_up = Some(Arc::new(move |attempts, elapsed, last, f| {
+            on_give_up(attempts, elapsed, last, f);
+            Box::pin(std::future::ready(()))
+        }));
+        self
+    }
+
+    #[must_use]
+    pub fn on_give_up_async<G, Fut>(mut self, on_give_up: G) -> Self
+    where
+        G: Fn(usize, Duration, Option<&R>, &F) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = ()> + Send + 'a,
+    {
+        //! `on_give_up` whose future is awaited before the loop returns its error
+        //!     such as flushing a batch of metrics or a trace
+        //!     that would otherwise be lost if the process exits right after the error comes back
+        //! the returned future can not borrow the errors
+        //!     so take whatever is needed from them before the `async` block
+        //! replaces any `on_give_up`, there is only one of either
+        self.on_give_up = Some(Arc::new(move |attempts, elapsed, last, f| {
+            Box::pin(on_give_up(attempts, elapsed, last, f))
+        }));
+        self
+    }
+
+    #[must_use]
+    pub fn attempt_timeout<T>(mut self, attempt_timeout: Duration, on_timeout: T) -> Self
+    where
+        T: Fn(Duration) -> R + Send + Sync + 'a,
+    {
+        //! an attempt still running after `attempt_timeout` is dropped
+        //!     and counts as the recoverable error that `on_timeout` makes
+        //!     from how long that attempt was given, such as a timeout error carrying the duration
+        //! with a `deadline` or `total_timeout` as well, each attempt gets whichever is shorter
+        //!     of `attempt_timeout` and the time left before the deadline
+        //!     so that no attempt runs past the deadline
+        //! the timing is done by this builder's sleeper
+        self.attempt_timeout = Some((attempt_timeout, Arc::new(on_timeout)));
+        self
+    }
+
+    #[must_use]
+    pub fn clock_granularity(mut self, granularity: Duration) -> Self {
+        //! for platforms whose timers are coarse, such as 10ms or worse
+        //! every wait is rounded up to a whole number of `granularity`
+        //!     before `max_wait` is applied, so `max_wait` still wins
+        //! the instants recorded in the history and given to `wait_decision`
+        //!     are rounded down to a whole number of `granularity` after the loop started
+        //!     so that the gaps between failures that a policy sees are not timer noise
+        //! a zero `granularity` changes nothing
+        self.granularity = Some(granularity).filter(|granularity| !granularity.is_zero());
+        self
+    }
+
+    #[must_use]
+    pub fn settle(mut self, settle: Duration) -> Self {
+        //! after a success that came only after at least one recoverable error
+        //!     wait `settle` before giving it back, to let a dependency that just recovered stabilize
+        //! a success on the first attempt is given back right away
+        //! the wait is done by this builder's sleeper and skipped like any other under `test-no-sleep`
+        self.settle = Some(settle);
+        self
+    }
+
+    #[must_use]
+    pub fn gate(mut self, gate: &'a RetryGate) -> Self {
+        //! before each retry, after its wait, hold off for as long as `gate` is paused
+        //! with a `deadline` or `total_timeout` as well, the loop gives up
+        //!     with `GiveUpReason::DeadlineExceeded` if the gate is still paused at the deadline
+        //!     and otherwise waits for as long as the gate stays paused
+        self.gate = Some(gate);
+        self
+    }
+
+    #[must_use]
+    pub fn retry_window<W>(mut self, allowed: W) -> Self
+    where
+        W: Fn(Instant) -> bool + Send + Sync + 'a,
+    {
+        //! only retry when `allowed` says so, such as between 06:00 and 22:00 for a batch job
+        //!     so that it does not keep failing and paging someone at night
+        //! it is asked about the time the retry would start, after its wait
+        //!     and when that is false the loop gives up with `GiveUpReason::OutsideWindow`
+        //! the first attempt of every call goes ahead whatever `allowed` says
+        //! replaces any `retry_window_wait`, there is only one window
+        self.window = Some((Arc::new(allowed), None));
+        self
+    }
+
+    #[must_use]
+    pub fn retry_window_wait<W>(mut self, allowed: W, poll_every: Duration) -> Self
+    where
+        W: Fn(Instant) -> bool + Send + Sync + 'a,
+    {
+        //! `retry_window` that holds off retries until the window opens again instead of giving up
+        //!     asking `allowed` again every `poll_every`, after the wait for the retry and any `gate`
+        //!     so the retry starts at most `poll_every` after the window opened
+        //! with a `deadline` or `total_timeout` as well, the loop gives up
+        //!     with `GiveUpReason::OutsideWindow` if the window is still closed at the deadline
+        //! a zero `poll_every` is taken as a millisecond so that the loop does not spin
+        self.window = Some((
+            Arc::new(allowed),
+            Some(poll_every.max(Duration::from_millis(1))),
+        ));
+        self
+    }
+
+    #[cfg(feature = "governor")]
+    #[must_use]
+    pub fn rate_limiter(mut self, limiter: &'a governor::DefaultDirectRateLimiter) -> Self {
+        //! wait for a permit from `limiter` before every attempt, the first one included
+        //!     so that all the loops sharing `limiter` keep to its rate between them
+        //! the time spent waiting for a permit is not part of any attempt
+        //!     so it does not count against `attempt_timeout`, but it does against a deadline
+        self.permit = Some(Arc::new(move || Box::pin(limiter.until_ready())));
+        self
+    }
+
+    #[must_use]
+    pub fn failure_window(mut self, window: Arc<FailureWindow>) -> Self {
+        //! report the outcome of every attempt to `window`, such as the one a `RateBasedBackoff` reads
+        //!     so the attempts do not have to record their own successes and failures
+        //! an attempt that timed out or whose fatal error was promoted counts as a failure
+        //!     and fatal errors are not recorded, as with `FailureWindow::observe`
+        self.failure_window = Some(window);
+        self
+    }
+
+    fn deadline_from(&self, started: Instant) -> Option<Instant> {
+        let relative = self
+            .total_timeout
+            .and_then(|timeout| started.checked_add(timeout));
+        match (self.deadline, relative) {
+            (Some(absolute), Some(relative)) => Some(absolute.min(relative)),
+            (absolute, relative) => absolute.or(relative),
+        }
+    }
+}
+
+impl<R, F, S> RetryBuilder<'_, R, F, S>
+where
+    R: Retryable<FatalError = F>,
+    S: Sleeper,
+{
+    pub async fn run<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<SuccessType, F>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+    {
+        //! the same loop as `repeatedly_try` with whichever hooks were set on this builder
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        self.run_detailed(do_this_function, arg, loggers)
+            .await
+            .map_err(GaveUp::into_error)
+    }
+
+    pub async fn run_detailed<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<SuccessType, GaveUp<F>>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+    {
+        //! `run` but when it gives up, the fatal error comes with why and after how many attempts
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        self.run_with_tracer(do_this_function, arg, (), loggers)
+            .await
+            .map(|(z, _)| z)
+    }
+
+    pub async fn run_map_final_error<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+        MapErr,
+        F2,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+        map_final_error: MapErr,
+    ) -> Result<SuccessType, F2>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+        MapErr: FnOnce(F, RetryStats) -> F2,
+    {
+        //! `run_detailed` with the fatal error turned into whatever `map_final_error` makes of it
+        //!     and how the loop went, whether the error came directly or the retries ran out
+        //!     so that the error can be enriched in one place instead of at every call site
+        //! the loggers see the fatal error from before `map_final_error`
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        let started = Instant::now();
+        self.run_detailed(do_this_function, arg, loggers)
+            .await
+            .map_err(|gave_up| {
+                let stats = RetryStats {
+                    attempts: gave_up.attempts,
+                    elapsed: started.elapsed(),
+                    reason: gave_up.reason,
+                };
+                map_final_error(gave_up.error, stats)
+            })
+    }
+
+    pub async fn run_with_history<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<(SuccessType, Vec<(R, Instant)>), GaveUp<F>>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+    {
+        //! `run_detailed` but a success comes with the recoverable errors it took to get there
+        //!     oldest first, for reporting what a call survived
+        //! errors from before one whose `resets_history` was true are not in it
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        self.run_with_tracer(do_this_function, arg, (), loggers)
+            .await
+    }
+
+    pub async fn run_with_context<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<SuccessType, GaveUp<F>>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType, RetryContext) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+    {
+        //! `run_detailed` with each attempt also given a `RetryContext`
+        //!     saying how many attempts and how much time are left
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        let deadline = self.deadline_from(Instant::now());
+        let max_attempts = self.max_attempts;
+        let with_context = |arg| {
+            do_this_function(
+                arg,
+                RetryContext {
+                    attempt: current_attempt().unwrap_or(1),
+                    max_attempts,
+                    deadline,
+                },
+            )
+        };
+        self.run_detailed(with_context, arg, loggers).await
+    }
+
+    pub async fn run_traced<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        trace: Option<&mut Vec<TraceEvent>>,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<SuccessType, GaveUp<F>>
+    where
+        R: Debug,
+        F: Debug,
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+    {
+        //! `run_detailed` that also appends a `TraceEvent` to `trace` for every attempt as it finishes
+        //!     unlike the loggers, which only hear about the errors once the loop has given up
+        //! with `trace` None nothing is formatted
+        //! # Errors
+        //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a fatal error directly
+        self.run_with_tracer(do_this_function, arg, trace, loggers)
+            .await
+            .map(|(z, _)| z)
+    }
+
+    async fn run_with_tracer<
+        SuccessType,
+        ArgType,
+        OneTryFun,
+        FailLogContext,
+        Fut0,
+        FatalLoggerType,
+        RecoverableLoggerType,
+        TracerType,
+    >(
+        &self,
+        do_this_function: OneTryFun,
+        arg: ArgType,
+        mut tracer: TracerType,
+        loggers: (
+            &mut FailLogContext,
+            Option<FatalLoggerType>,
+            Option<RecoverableLoggerType>,
+        ),
+    ) -> Result<(SuccessType, Vec<(R, Instant)>), GaveUp<F>>
+    where
+        ArgType: Sized + Clone,
+        OneTryFun: Fn(ArgType) -> Fut0,
+        Fut0: Future<Output = RetryableResult<SuccessType, R, F>>,
+        FatalLoggerType: Fn(&F, Instant, &mut FailLogContext),
+        RecoverableLoggerType: Fn(&R, Instant, &mut FailLogContext),
+        TracerType: Tracer<R, F>,
+    {
+        let loop_start = Instant::now();
+        let deadline = self.deadline_from(loop_start);
+        let mut clock = LoopClock::default();
+        let mut my_retriable_failures = Vec::<(R, Instant)>::new();
+        let mut my_attempt_durations = Vec::<Duration>::new();
+        let mut forgotten = 0;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if let Some(permit) = &self.permit {
+                permit().await;
+            }
+            metrics::attempt();
+            let attempt_start = Instant::now();
+            let one_attempt = as_attempt(attempt, || do_this_function(arg.clone()));
+            let outcome = match &self.attempt_timeout {
+                Some((attempt_timeout, on_timeout)) => {
+                    let limit =
+                        effective_attempt_timeout(*attempt_timeout, deadline, attempt_start);
+                    first_to_finish(one_attempt, self.sleeper.sleep(limit))
+                        .await
+                        .unwrap_or_else(|| RetryableResult::Retryable(on_timeout(limit)))
+                }
+                None => one_attempt.await,
+            };
+            let cur_trial = match outcome {
+                RetryableResult::Fatal(f) => {
+                    match self.promote_fatal.as_ref().and_then(|p| p(&f)) {
+                        Some(r) => RetryableResult::Retryable(r),
+                        None => RetryableResult::Fatal(f),
+                    }
+                }
+                other => other,
+            };
+            if let Some(window) = &self.failure_window {
+                window.observe(&cur_trial);
+            }
+            match cur_trial {
+                RetryableResult::GoodResult(z) => {
+                    tracer.good(attempt, attempt_start);
+                    if let Some(settle) = self.settle.filter(|_| attempt > 1) {
+                        if !clock.skip(settle) {
+                            self.sleeper.sleep(settle).await;
+                        }
+                    }
+                    return Ok((z, my_retriable_failures));
+                }
+                RetryableResult::Retryable(r) => {
+                    metrics::recoverable();
+                    let now = Instant::now();
+                    let this_attempt_took = now.saturating_duration_since(attempt_start);
+                    let now = clock.at(now);
+                    let this_time = self
+                        .granularity
+                        .map_or(now, |granularity| round_down(now, loop_start, granularity));
+                    if r.resets_history() {
+                        my_retriable_failures.clear();
+                        my_attempt_durations.clear();
+                        forgotten = 0;
+                    }
+                    let vetoed = match &self.should_retry {
+                        Some(should_retry) => !should_retry(&r).await,
+                        None => false,
+                    };
+                    let decision = r.timed_wait_decision(
+                        this_time,
+                        this_attempt_took,
+                        &my_retriable_failures,
+                        &my_attempt_durations,
+                    );
+                    let wanted = match decision {
+                        WaitDecision::Wait(how_long_to_wait) => how_long_to_wait,
+                        WaitDecision::Immediate | WaitDecision::GiveUp => Duration::ZERO,
+                    };
+                    let decision = match (decision, self.granularity) {
+                        (WaitDecision::Wait(how_long_to_wait), Some(granularity)) => {
+                            WaitDecision::Wait(round_up(how_long_to_wait, granularity))
+                        }
+                        (decision, _) => decision,
+                    };
+                    let decision = match (decision, self.max_wait) {
+                        (WaitDecision::Wait(how_long_to_wait), Some(max_wait)) => {
+                            WaitDecision::Wait(how_long_to_wait.min(max_wait))
+                        }
+                        (decision, _) => decision,
+                    };
+                    let decision = match decision {
+                        WaitDecision::Wait(how_long_to_wait) => {
+                            WaitDecision::Wait(how_long_to_wait.min(self.implausible_wait))
+                        }
+                        decision => decision,
+                    };
+                    let give_up_reason = match decision {
+                        _ if vetoed => Some(GiveUpReason::Vetoed),
+                        WaitDecision::GiveUp
+                            if self.strict && forgotten + my_retriable_failures.len() == 0 =>
+                        {
+                            Some(GiveUpReason::NoRetryAllowed)
+                        }
+                        WaitDecision::GiveUp => Some(GiveUpReason::PolicyGaveUp),
+                        _ if self.max_attempts.is_some_and(|max| attempt >= max) => {
+                            Some(GiveUpReason::AttemptsExhausted)
+                        }
+                        _ if self.health.as_ref().is_some_and(|healthy| !healthy()) => {
+                            Some(GiveUpReason::Unhealthy)
+                        }
+                        _ if self.outside_window(this_time, decision) => {
+                            Some(GiveUpReason::OutsideWindow)
+                        }
+                        WaitDecision::Wait(how_long_to_wait)
+                            if deadline.is_some_and(|deadline| {
+                                this_time
+                                    .checked_add(how_long_to_wait)
+                                    .is_none_or(|next_attempt| next_attempt > deadline)
+                            }) =>
+                        {
+                            Some(GiveUpReason::DeadlineExceeded)
+                        }
+                        WaitDecision::Immediate
+                            if deadline.is_some_and(|deadline| this_time > deadline) =>
+                        {
+                            Some(GiveUpReason::DeadlineExceeded)
+                        }
+                        _ => None,
+                    };
+                    tracer.recoverable(
+                        attempt,
+                        attempt_start,
+                        &r,
+                        if give_up_reason.is_some() {
+                            WaitDecision::GiveUp
+                        } else {
+                            decision
+                        },
+                    );
+                    if let Some(reason) = give_up_reason {
+                        let f = if matches!(
+                            reason,
+                            GiveUpReason::PolicyGaveUp
+                                | GiveUpReason::NoRetryAllowed
+                                | GiveUpReason::AttemptsExhausted
+                        ) {
+                            r.escalate(&my_retriable_failures)
+                        } else {
+                            r.to_fatal()
+                        };
+                        metrics::exhausted();
+                        let summary =
+                            self.summarize(forgotten, &my_retriable_failures, &f, loop_start);
+                        summary.await;
+                        return Err(GaveUp {
+                            error: log_and_give_up(loggers, &my_retriable_failures, f, this_time),
+                            reason,
+                            attempts: forgotten + my_retriable_failures.len() + 1,
+                        });
+                    }
+                    my_retriable_failures.push((r, this_time));
+                    my_attempt_durations.push(this_attempt_took);
+                    if let Some(over) = self
+                        .history_limit
+                        .and_then(|limit| my_retriable_failures.len().checked_sub(limit))
+                    {
+                        my_retriable_failures.drain(..over);
+                        my_attempt_durations.drain(..over);
+                        forgotten += over;
+                    }
+                    let waiting = match decision {
+                        WaitDecision::Wait(how_long_to_wait) => how_long_to_wait,
+                        WaitDecision::Immediate | WaitDecision::GiveUp => Duration::ZERO,
+                    };
+                    metrics::wait(waiting);
+                    if let Some(on_wait) = &self.on_wait {
+                        on_wait(&WaitInfo {
+                            attempt,
+                            wanted,
+                            waiting,
+                            truncated: waiting < wanted,
+                        });
+                    }
+                    if let WaitDecision::Wait(how_long_to_wait) = decision {
+                        if !clock.skip(how_long_to_wait) {
+                            self.sleeper.sleep(how_long_to_wait).await;
+                        }
+                    }
+                    let held_back = if !self.through_gate(deadline).await {
+                        Some(GiveUpReason::DeadlineExceeded)
+                    } else if !self.until_window_open(deadline).await {
+                        Some(GiveUpReason::OutsideWindow)
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = held_back {
+                        if let Some((r, this_time)) = my_retriable_failures.pop() {
+                            tracer.gave_up_waiting();
+                            metrics::exhausted();
+                            let f = r.to_fatal();
+                            let summary =
+                                self.summarize(forgotten, &my_retriable_failures, &f, loop_start);
+                            summary.await;
+                            return Err(GaveUp {
+                                error: log_and_give_up(
+                                    loggers,
+                                    &my_retriable_failures,
+                                    f,
+                                    this_time,
+                                ),
+                                reason,
+                                attempts: forgotten + my_retriable_failures.len() + 1,
+                            });
+                        }
+                    }
+                }
+                RetryableResult::Fatal(f) => {
+                    let this_time = clock.now();
+                    tracer.fatal(attempt, attempt_start, &f);
+                    metrics::fatal();
+                    let summary = self.summarize(forgotten, &my_retriable_failures, &f, loop_start);
+                    summary.await;
+                    return Err(GaveUp {
+                        error: log_and_give_up(loggers, &my_retriable_failures, f, this_time),
+                        reason: GiveUpReason::Fatal,
+                        attempts: forgotten + my_retriable_failures.len() + 1,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'a, R, F, S> RetryBuilder<'a, R, F, S>
+where
+    R: Retryable<FatalError = F>,
+    S: Sleeper,
+{
+    async fn through_gate(&self, deadline: Option<Instant>) -> bool {
+        //! false when the deadline came while the gate was still paused
+        let Some(gate) = self.gate else {
+            return true;
+        };
+        match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                first_to_finish(gate.until_open(), self.sleeper.sleep(left))
+                    .await
+                    .is_some()
+            }
+            None => {
+                gate.until_open().await;
+                true
+            }
+        }
+    }
+
+    async fn until_window_open(&self, deadline: Option<Instant>) -> bool {
+        //! false when the deadline came while `retry_window_wait` was still closed
+        let Some((allowed, Some(poll_every))) = &self.window else {
+            return true;
+        };
+        loop {
+            let now = Instant::now();
+            if allowed(now) {
+                return true;
+            }
+            let pause = match deadline {
+                Some(deadline) if now >= deadline => return false,
+                Some(deadline) => (*poll_every).min(deadline - now),
+                None => *poll_every,
+            };
+            self.sleeper.sleep(pause).await;
+        }
+    }
+
+    fn outside_window(&self, this_time: Instant, decision: WaitDecision) -> bool {
+        //! for `retry_window`, whether the retry would start while it is closed
+        let Some((allowed, None)) = &self.window else {
+            return false;
+        };
+        let next_attempt = match decision {
+            WaitDecision::Wait(how_long_to_wait) => this_time.checked_add(how_long_to_wait),
+            WaitDecision::Immediate | WaitDecision::GiveUp => Some(this_time),
+        };
+        !next_attempt.is_some_and(|next_attempt| allowed(next_attempt))
+    }
+
+    fn summarize(
+        &self,
+        forgotten: usize,
+        history: &[(R, Instant)],
+        f: &F,
+        loop_start: Instant,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        //! the future is made before it is awaited so that it does not hold on to the errors
+        match &self.on_give_up {
+            Some(on_give_up) => on_give_up(
+                forgotten + history.len() + 1,
+                loop_start.elapsed(),
+                history.last().map(|(r, _)| r),
+                f,
+            ),
+            None => Box::pin(std::future::ready(())),
+        }
+    }
+}
+
+fn round_up(wait: Duration, granularity: Duration) -> Duration {
+    let ticks = wait.as_nanos().div_ceil(granularity.as_nanos());
+    u32::try_from(ticks)
+        .ok()
+        .and_then(|ticks| granularity.checked_mul(ticks))
+        .unwrap_or(Duration::MAX)
+}
+
+fn round_down(when: Instant, origin: Instant, granularity: Duration) -> Instant {
+    let ticks = when.saturating_duration_since(origin).as_nanos() / granularity.as_nanos();
+    u32::try_from(ticks)
+        .ok()
+        .and_then(|ticks| granularity.checked_mul(ticks))
+        .and_then(|since_origin| origin.checked_add(since_origin))
+        .unwrap_or(when)
+}
+
+fn effective_attempt_timeout(
+    attempt_timeout: Duration,
+    deadline: Option<Instant>,
+    now: Instant,
+) -> Duration {
+    deadline.map_or(attempt_timeout, |deadline| {
+        attempt_timeout.min(deadline.saturating_duration_since(now))
+    })
+}
+
+async fn first_to_finish<Fut, Timer>(attempt: Fut, timer: Timer) -> Option<Fut::Output>
+where
+    Fut: Future,
+    Timer: Future<Output = ()>,
+{
+    let mut attempt = pin!(attempt);
+    let mut timer = pin!(timer);
+    poll_fn(|cx| {
+        if let Poll::Ready(out) = attempt.as_mut().poll(cx) {
+            return Poll::Ready(Some(out));
+        }
+        timer.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    #[derive(Debug, PartialEq)]
+    struct Transient(u16);
+
+    impl Retryable for Transient {
+        type FatalError = u16;
+
+        fn to_fatal(self) -> Self::FatalError {
+            self.0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[allow(dead_code)]
+    fn no_fatal_logger(_: &u16, _: std::time::Instant, _: &mut Vec<u16>) {}
+
+    #[allow(dead_code)]
+    fn recoverable_logger(e: &Transient, _: std::time::Instant, ctx: &mut Vec<u16>) {
+        ctx.push(e.0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn promoted_fatal_errors_are_retried() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match which {
+                    0 | 1 => RetryableResult::<&str, Transient, u16>::Fatal(500),
+                    2 => RetryableResult::Fatal(404),
+                    _ => RetryableResult::GoodResult("done"),
+                }
+            }
+        };
+
+        let promoting =
+            RetryBuilder::new().promote_fatal(|f: &u16| (*f == 500).then_some(Transient(*f)));
+        let mut seen = Vec::new();
+        let z = promoting
+            .run(
+                one_try,
+                (),
+                (&mut seen, Some(no_fatal_logger), Some(recoverable_logger)),
+            )
+            .await;
+        assert_eq!(z, Err(404));
+        assert_eq!(seen, vec![500, 500]);
+
+        attempts.store(0, Ordering::SeqCst);
+        let z = RetryBuilder::new()
+            .run(
+                one_try,
+                (),
+                (
+                    &mut Vec::new(),
+                    Some(no_fatal_logger),
+                    Some(recoverable_logger),
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(500));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn detailed_give_up_reason() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        let no_fatal_logger = None::<fn(&u16, std::time::Instant, &mut ())>;
+        let no_recoverable_logger = None::<fn(&Transient, std::time::Instant, &mut ())>;
+        let z = RetryBuilder::new()
+            .run_detailed(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (&mut (), no_fatal_logger, no_recoverable_logger),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::PolicyGaveUp,
+                attempts: 4,
+            })
+        );
+        let z = RetryBuilder::new()
+            .run_detailed(
+                |()| async { RetryableResult::<(), Transient, u16>::Fatal(400) },
+                (),
+                (&mut (), no_fatal_logger, no_recoverable_logger),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 400,
+                reason: GiveUpReason::Fatal,
+                attempts: 1,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn closed_window_gives_up() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        let open = AtomicBool::new(true);
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 1 {
+                open.store(false, Ordering::SeqCst);
+            }
+            async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) }
+        };
+        let z = RetryBuilder::new()
+            .retry_window(|_| open.load(Ordering::SeqCst))
+            .run_detailed(
+                one_try,
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::OutsideWindow,
+                attempts: 2,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn retry_waits_for_window() {
+        use super::{RetryBuilder, TraceOutcome};
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::{RetryableResult, WaitDecision};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+        let no_loggers = (
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Transient, Instant, &mut ())>,
+        );
+        let attempts = AtomicUsize::new(0);
+        let started = Instant::now();
+        let opens = started + Duration::from_millis(30);
+        let z = RetryBuilder::new()
+            .retry_window_wait(|when| when >= opens, Duration::from_millis(5))
+            .run_detailed(
+                |()| async {
+                    if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        RetryableResult::Retryable(Transient(503))
+                    } else {
+                        RetryableResult::GoodResult(7)
+                    }
+                },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Ok(7));
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        let never_opens = RetryBuilder::new()
+            .retry_window_wait(|_| false, Duration::from_millis(5))
+            .total_timeout(Duration::from_millis(20));
+        let mut trace = Vec::new();
+        let z = never_opens
+            .run_traced(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                Some(&mut trace),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::OutsideWindow,
+                attempts: 1,
+            })
+        );
+        assert_eq!(
+            trace
+                .iter()
+                .map(|event| event.outcome.clone())
+                .collect::<Vec<_>>(),
+            vec![TraceOutcome::Recoverable {
+                error: "Transient(503)".to_string(),
+                decision: WaitDecision::GiveUp,
+            }]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn unhealthy_dependency_gives_up() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        let healthy = AtomicBool::new(true);
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 1 {
+                healthy.store(false, Ordering::SeqCst);
+            }
+            async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) }
+        };
+        let z = RetryBuilder::new()
+            .health_check(|| healthy.load(Ordering::SeqCst))
+            .run_detailed(
+                one_try,
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::Unhealthy,
+                attempts: 2,
+            })
+        );
+    }
+
+    #[allow(dead_code)]
+    enum StreamHiccup {
+        Stale,
+        Fresh,
+    }
+
+    impl Retryable for StreamHiccup {
+        type FatalError = usize;
+
+        fn to_fatal(self) -> Self::FatalError {
+            0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::ZERO)
+        }
+
+        fn resets_history(&self) -> bool {
+            matches!(self, StreamHiccup::Fresh)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn fresh_error_resets_history() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match which {
+                    0 | 1 | 3 => RetryableResult::Retryable(StreamHiccup::Stale),
+                    2 => RetryableResult::Retryable(StreamHiccup::Fresh),
+                    _ => RetryableResult::GoodResult(which),
+                }
+            }
+        };
+        let z = RetryBuilder::new()
+            .run(
+                one_try,
+                (),
+                (
+                    &mut (),
+                    None::<fn(&usize, std::time::Instant, &mut ())>,
+                    None::<fn(&StreamHiccup, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Ok(4));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn async_veto_makes_error_fatal() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                RetryableResult::<(), _, u16>::Retryable(Transient(if which == 0 {
+                    503
+                } else {
+                    502
+                }))
+            }
+        };
+        let z = RetryBuilder::new()
+            .should_retry_async(|r: &Transient| {
+                let code = r.0;
+                async move {
+                    async_std::task::yield_now().await;
+                    code != 502
+                }
+            })
+            .run_detailed(
+                one_try,
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 502,
+                reason: GiveUpReason::Vetoed,
+                attempts: 2,
+            })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cloned_builder_shares_hooks() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let promotions = AtomicUsize::new(0);
+        let configured = RetryBuilder::new().promote_fatal(|f: &u16| {
+            promotions.fetch_add(1, Ordering::SeqCst);
+            (*f == 500).then_some(Transient(*f))
+        });
+        for builder in [configured.clone(), configured] {
+            let z = builder
+                .run(
+                    |()| async { RetryableResult::<(), Transient, u16>::Fatal(500) },
+                    (),
+                    (
+                        &mut (),
+                        None::<fn(&u16, std::time::Instant, &mut ())>,
+                        None::<fn(&Transient, std::time::Instant, &mut ())>,
+                    ),
+                )
+                .await;
+            assert_eq!(z, Err(500));
+        }
+        assert_eq!(promotions.load(Ordering::SeqCst), 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn total_timeout_from_start() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+        let attempts = AtomicUsize::new(0);
+        let slow_after_first = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which > 0 {
+                    async_std::task::sleep(Duration::from_millis(50)).await;
+                }
+                RetryableResult::<(), _, u16>::Retryable(Transient(503))
+            }
+        };
+        let no_loggers = (
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Transient, Instant, &mut ())>,
+        );
+        let within_budget = RetryBuilder::new().total_timeout(Duration::from_secs(60));
+        let z = within_budget
+            .run_detailed(slow_after_first, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(z.map_err(|e| e.reason), Err(GiveUpReason::PolicyGaveUp));
+
+        attempts.store(0, Ordering::SeqCst);
+        let tight = RetryBuilder::new().total_timeout(Duration::from_millis(25));
+        let z = tight
+            .run_detailed(slow_after_first, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::DeadlineExceeded,
+                attempts: 2,
+            })
+        );
+
+        let already_passed = RetryBuilder::new()
+            .total_timeout(Duration::from_secs(60))
+            .deadline(Instant::now());
+        let z = already_passed
+            .run_detailed(slow_after_first, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(
+            z.map_err(|e| (e.reason, e.attempts)),
+            Err((GiveUpReason::DeadlineExceeded, 1))
+        );
+    }
+
+    #[allow(dead_code)]
+    struct Flapping;
+
+    impl Retryable for Flapping {
+        type FatalError = String;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "flapping".to_string()
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::ZERO)
+        }
+
+        fn escalate(
+            self,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Self::FatalError {
+            format!(
+                "PAGE: flapping {} times in a row",
+                previous_retriable_failures.len() + 1
+            )
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn exhaustion_escalates() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let no_loggers = (
+            None::<fn(&String, std::time::Instant, &mut ())>,
+            None::<fn(&Flapping, std::time::Instant, &mut ())>,
+        );
+        let z = RetryBuilder::new()
+            .run(
+                |()| async { RetryableResult::<(), _, String>::Retryable(Flapping) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Err("PAGE: flapping 3 times in a row".to_string()));
+
+        let healthy = AtomicBool::new(false);
+        let z = RetryBuilder::new()
+            .health_check(|| healthy.load(Ordering::SeqCst))
+            .run(
+                |()| async { RetryableResult::<(), _, String>::Retryable(Flapping) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Err("flapping".to_string()));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Clone, Default)]
+    struct RecordingSleeper(std::sync::Arc<std::sync::Mutex<Vec<std::time::Duration>>>);
+
+    impl crate::sleep::Sleeper for RecordingSleeper {
+        async fn sleep(&self, how_long: std::time::Duration) {
+            self.0.lock().unwrap().push(how_long);
+        }
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn custom_sleeper_does_the_waiting() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        fn assert_send<T: Send>(t: T) -> T {
+            t
+        }
+        let sleeper = RecordingSleeper::default();
+        let z = assert_send(RetryBuilder::new().sleeper(sleeper.clone()).run(
+            |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+            (),
+            (
+                &mut (),
+                None::<fn(&u16, std::time::Instant, &mut ())>,
+                None::<fn(&Transient, std::time::Instant, &mut ())>,
+            ),
+        ))
+        .await;
+        assert_eq!(z, Err(503));
+        assert_eq!(
+            *sleeper.0.lock().unwrap(),
+            vec![std::time::Duration::from_millis(1); 3]
+        );
+    }
+
+    #[allow(dead_code)]
+    struct NeverAgain;
+
+    impl Retryable for NeverAgain {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "never again"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn strict_mode_flags_policy_that_never_retries() {
+        use super::RetryBuilder;
+        use crate::give_up::GiveUpReason;
+        use crate::retryable::RetryableResult;
+        let never = |()| async { RetryableResult::<(), _, _>::Retryable(NeverAgain) };
+        let no_loggers = (
+            None::<fn(&&'static str, std::time::Instant, &mut ())>,
+            None::<fn(&NeverAgain, std::time::Instant, &mut ())>,
+        );
+        let z = RetryBuilder::new()
+            .run_detailed(never, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(z.map_err(|e| e.reason), Err(GiveUpReason::PolicyGaveUp));
+        let z = RetryBuilder::new()
+            .strict()
+            .run_detailed(never, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(
+            z.map_err(|e| (e.error, e.reason)),
+            Err(("never again", GiveUpReason::NoRetryAllowed))
+        );
+        let z = RetryBuilder::new()
+            .strict()
+            .run_detailed(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z.map_err(|e| e.reason), Err(GiveUpReason::PolicyGaveUp));
+    }
+
+    #[allow(dead_code)]
+    struct SlowToRecover;
+
+    impl Retryable for SlowToRecover {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            let millis = [1, 5, 50];
+            millis
+                .get(previous_retriable_failures.len())
+                .map(|ms| std::time::Duration::from_millis(*ms))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn clamped_waits_are_reported() {
+        use super::{RetryBuilder, WaitInfo};
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        use std::time::Duration;
+        let seen = Mutex::new(Vec::new());
+        let z = RetryBuilder::new()
+            .max_wait(Duration::from_millis(5))
+            .on_wait(|info: &WaitInfo| seen.lock().unwrap().push(*info))
+            .sleeper(RecordingSleeper::default())
+            .run(
+                |()| async { RetryableResult::<(), _, ()>::Retryable(SlowToRecover) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&(), std::time::Instant, &mut ())>,
+                    None::<fn(&SlowToRecover, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(()));
+        let seen = seen.into_inner().unwrap();
+        let summary: Vec<_> = seen
+            .iter()
+            .map(|info| {
+                (
+                    info.attempt,
+                    info.waiting,
+                    info.truncated,
+                    info.truncated_by(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Duration::from_millis(1), false, Duration::ZERO),
+                (2, Duration::from_millis(5), false, Duration::ZERO),
+                (3, Duration::from_millis(5), true, Duration::from_millis(45)),
+            ]
+        );
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn one_summary_on_give_up() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        use std::time::Duration;
+        let summaries = Mutex::new(Vec::new());
+        let builder = RetryBuilder::new().on_give_up(
+            |attempts, elapsed: Duration, last: Option<&Transient>, fatal: &u16| {
+                summaries
+                    .lock()
+                    .unwrap()
+                    .push((attempts, elapsed, last.map(|t| t.0), *fatal));
+            },
+        );
+        let mut replayed = Vec::new();
+        let z = builder
+            .run(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut replayed,
+                    Some(no_fatal_logger),
+                    Some(recoverable_logger),
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(503));
+        assert_eq!(replayed, vec![503, 503, 503]);
+        let z = builder
+            .run(
+                |()| async { RetryableResult::<(), Transient, _>::Fatal(404) },
+                (),
+                (
+                    &mut replayed,
+                    None::<fn(&u16, std::time::Instant, &mut Vec<u16>)>,
+                    None::<fn(&Transient, std::time::Instant, &mut Vec<u16>)>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(404));
+        drop(builder);
+        let summaries = summaries.into_inner().unwrap();
+        let without_elapsed: Vec<_> = summaries
+            .iter()
+            .map(|(attempts, _, last, fatal)| (*attempts, *last, *fatal))
+            .collect();
+        assert_eq!(without_elapsed, vec![(4, Some(503), 503), (1, None, 404)]);
+        assert!(summaries[0].1 >= Duration::from_millis(3));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn async_summary_flushed_before_returning() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        use std::time::Duration;
+        let flushed = Mutex::new(Vec::new());
+        let z = RetryBuilder::new()
+            .on_give_up_async(
+                |attempts, _: Duration, _: Option<&Transient>, fatal: &u16| {
+                    let line = format!("gave up after {attempts} attempts on {fatal}");
+                    let flushed = &flushed;
+                    async move {
+                        async_std::task::yield_now().await;
+                        flushed.lock().unwrap().push(line);
+                    }
+                },
+            )
+            .run(
+                |()| async { RetryableResult::<(), Transient, _>::Fatal(404) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(404));
+        assert_eq!(
+            *flushed.lock().unwrap(),
+            vec!["gave up after 1 attempts on 404"]
+        );
+    }
+
+    #[cfg(feature = "governor")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn attempts_wait_for_permits() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use governor::{Quota, RateLimiter};
+        use std::time::{Duration, Instant};
+        let one_every_20ms = Quota::with_period(Duration::from_millis(20)).expect("nonzero period");
+        let limiter = RateLimiter::direct(one_every_20ms);
+        let started = Instant::now();
+        let z = RetryBuilder::new()
+            .rate_limiter(&limiter)
+            .run(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(503));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn settles_only_after_a_recovery() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let no_loggers = (
+            None::<fn(&u16, std::time::Instant, &mut ())>,
+            None::<fn(&Transient, std::time::Instant, &mut ())>,
+        );
+        let sleeper = RecordingSleeper::default();
+        let builder = RetryBuilder::new()
+            .settle(Duration::from_millis(250))
+            .sleeper(sleeper.clone());
+        let z = builder
+            .run(
+                |()| async { RetryableResult::<_, Transient, u16>::GoodResult("first try") },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Ok("first try"));
+        assert!(sleeper.0.lock().unwrap().is_empty());
+
+        let attempts = AtomicUsize::new(0);
+        let z = builder
+            .run(
+                |()| {
+                    let which = attempts.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if which == 0 {
+                            RetryableResult::Retryable(Transient(503))
+                        } else {
+                            RetryableResult::GoodResult("recovered")
+                        }
+                    }
+                },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Ok("recovered"));
+        assert_eq!(
+            *sleeper.0.lock().unwrap(),
+            vec![Duration::from_millis(1), Duration::from_millis(250)]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn final_error_enriched() {
+        use super::{RetryBuilder, RetryStats};
+        use crate::retryable::RetryableResult;
+        use std::time::Duration;
+        let no_loggers = (
+            None::<fn(&u16, std::time::Instant, &mut ())>,
+            None::<fn(&Transient, std::time::Instant, &mut ())>,
+        );
+        let enrich = |status: u16, stats: RetryStats| {
+            assert!(stats.elapsed < Duration::from_secs(5));
+            format!(
+                "{status} after {} attempts ({:?})",
+                stats.attempts, stats.reason
+            )
+        };
+        let builder = RetryBuilder::new();
+        let z = builder
+            .run_map_final_error(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+                enrich,
+            )
+            .await;
+        assert_eq!(z, Err(String::from("503 after 4 attempts (PolicyGaveUp)")));
+        let z = builder
+            .run_map_final_error(
+                |()| async { RetryableResult::<(), Transient, _>::Fatal(404) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+                enrich,
+            )
+            .await;
+        assert_eq!(z, Err(String::from("404 after 1 attempts (Fatal)")));
+        assert_eq!(
+            builder
+                .run_map_final_error(
+                    |()| async { RetryableResult::<_, Transient, u16>::GoodResult(7) },
+                    (),
+                    (&mut (), no_loggers.0, no_loggers.1),
+                    enrich,
+                )
+                .await,
+            Ok(7)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn history_kept_to_its_limit() {
+        use super::RetryBuilder;
+        use crate::give_up::GiveUpReason;
+        use crate::retryable::RetryableResult;
+        let mut replayed = Vec::new();
+        let z = RetryBuilder::new()
+            .history_limit(2)
+            .max_attempts(6)
+            .run_detailed(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut replayed,
+                    Some(no_fatal_logger),
+                    Some(recoverable_logger),
+                ),
+            )
+            .await;
+        assert_eq!(
+            z.map_err(|e| (e.reason, e.attempts)),
+            Err((GiveUpReason::AttemptsExhausted, 6))
+        );
+        assert_eq!(replayed, vec![503, 503]);
+    }
+
+    #[allow(dead_code)]
+    struct Overflowed;
+
+    impl Retryable for Overflowed {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            previous_retriable_failures
+                .is_empty()
+                .then_some(std::time::Duration::MAX)
+        }
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn implausible_waits_are_cut() {
+        use super::{RetryBuilder, WaitInfo};
+        use crate::give_up::GiveUpReason;
+        use crate::retryable::RetryableResult;
+        use crate::sleep::IMPLAUSIBLE_WAIT;
+        use std::sync::Mutex;
+        use std::time::Duration;
+        let no_loggers = (
+            None::<fn(&(), std::time::Instant, &mut ())>,
+            None::<fn(&Overflowed, std::time::Instant, &mut ())>,
+        );
+        let sleeper = RecordingSleeper::default();
+        let z = RetryBuilder::new()
+            .sleeper(sleeper.clone())
+            .run(
+                |()| async { RetryableResult::<(), _, ()>::Retryable(Overflowed) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Err(()));
+        assert_eq!(*sleeper.0.lock().unwrap(), vec![IMPLAUSIBLE_WAIT]);
+
+        let seen = Mutex::new(Vec::new());
+        let sleeper = RecordingSleeper::default();
+        let z = RetryBuilder::new()
+            .implausible_wait(Duration::from_millis(3))
+            .on_wait(|info: &WaitInfo| seen.lock().unwrap().push(*info))
+            .sleeper(sleeper.clone())
+            .run(
+                |()| async { RetryableResult::<(), _, ()>::Retryable(Overflowed) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z, Err(()));
+        assert_eq!(*sleeper.0.lock().unwrap(), vec![Duration::from_millis(3)]);
+        let seen = seen.into_inner().unwrap();
+        assert!(seen
+            .iter()
+            .all(|info| info.truncated && info.wanted == Duration::MAX));
+
+        let z = RetryBuilder::new()
+            .total_timeout(Duration::from_secs(60))
+            .sleeper(RecordingSleeper::default())
+            .run_detailed(
+                |()| async { RetryableResult::<(), _, ()>::Retryable(Overflowed) },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(z.map_err(|e| e.reason), Err(GiveUpReason::DeadlineExceeded));
+    }
+
+    #[test]
+    fn granularity_rounding() {
+        use super::{round_down, round_up};
+        use std::time::{Duration, Instant};
+        let tick = Duration::from_millis(10);
+        assert_eq!(round_up(Duration::ZERO, tick), Duration::ZERO);
+        assert_eq!(round_up(Duration::from_micros(1), tick), tick);
+        assert_eq!(round_up(tick, tick), tick);
+        assert_eq!(round_up(Duration::from_millis(11), tick), 2 * tick);
+        assert_eq!(round_up(Duration::MAX, tick), Duration::MAX);
+
+        let origin = Instant::now();
+        assert_eq!(round_down(origin, origin, tick), origin);
+        assert_eq!(
+            round_down(origin + Duration::from_millis(9), origin, tick),
+            origin
+        );
+        assert_eq!(
+            round_down(origin + Duration::from_millis(25), origin, tick),
+            origin + 2 * tick
+        );
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn waits_rounded_to_granularity() {
+        use super::RetryBuilder;
+        use crate::retryable::RetryableResult;
+        use std::time::Duration;
+        let sleeper = RecordingSleeper::default();
+        let z = RetryBuilder::new()
+            .clock_granularity(Duration::from_millis(10))
+            .sleeper(sleeper.clone())
+            .run(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(503));
+        assert_eq!(
+            *sleeper.0.lock().unwrap(),
+            vec![Duration::from_millis(10); 3]
+        );
+
+        let sleeper = RecordingSleeper::default();
+        let _ = RetryBuilder::new()
+            .clock_granularity(Duration::from_millis(10))
+            .max_wait(Duration::from_millis(4))
+            .sleeper(sleeper.clone())
+            .run(
+                |()| async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, std::time::Instant, &mut ())>,
+                    None::<fn(&Transient, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(
+            *sleeper.0.lock().unwrap(),
+            vec![Duration::from_millis(4); 3]
+        );
+    }
+
+    #[test]
+    fn attempt_timeout_never_outlasts_deadline() {
+        use super::effective_attempt_timeout;
+        use std::time::{Duration, Instant};
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(effective_attempt_timeout(second, None, now), second);
+        assert_eq!(
+            effective_attempt_timeout(second, Some(now + 5 * second), now),
+            second
+        );
+        assert_eq!(
+            effective_attempt_timeout(second, Some(now + second), now),
+            second
+        );
+        assert_eq!(
+            effective_attempt_timeout(second, Some(now + second / 4), now),
+            second / 4
+        );
+        assert_eq!(
+            effective_attempt_timeout(second, Some(now), now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            effective_attempt_timeout(second, Some(now), now + second),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hung_attempt_stops_at_deadline() {
+        use super::RetryBuilder;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+        let attempts = AtomicUsize::new(0);
+        let hangs_after_first = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which > 0 {
+                    std::future::pending::<()>().await;
+                }
+                RetryableResult::<(), _, u16>::Retryable(Transient(503))
+            }
+        };
+        let no_loggers = (
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Transient, Instant, &mut ())>,
+        );
+
+        let started = Instant::now();
+        let z = RetryBuilder::new()
+            .attempt_timeout(Duration::from_secs(60), |given| {
+                assert!(given <= Duration::from_millis(30));
+                Transient(504)
+            })
+            .total_timeout(Duration::from_millis(30))
+            .run_detailed(hangs_after_first, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 504,
+                reason: GiveUpReason::DeadlineExceeded,
+                attempts: 2,
+            })
+        );
+
+        attempts.store(0, Ordering::SeqCst);
+        let z = RetryBuilder::new()
+            .attempt_timeout(Duration::from_millis(5), |given| {
+                assert_eq!(given, Duration::from_millis(5));
+                Transient(504)
+            })
+            .total_timeout(Duration::from_secs(60))
+            .run_detailed(hangs_after_first, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(z.map_err(|e| e.reason), Err(GiveUpReason::PolicyGaveUp));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn trace_records_every_decision() {
+        use super::{RetryBuilder, TraceOutcome};
+        use crate::retryable::{RetryableResult, WaitDecision};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match which {
+                    0 | 1 => RetryableResult::<(), _, u16>::Retryable(Transient(503)),
+                    _ => RetryableResult::Fatal(404),
+                }
+            }
+        };
+        let no_fatal_logger = None::<fn(&u16, std::time::Instant, &mut ())>;
+        let no_recoverable_logger = None::<fn(&Transient, std::time::Instant, &mut ())>;
+        let mut trace = Vec::new();
+        let z = RetryBuilder::new()
+            .run_traced(
+                one_try,
+                (),
+                Some(&mut trace),
+                (&mut (), no_fatal_logger, no_recoverable_logger),
+            )
+            .await;
+        assert_eq!(z.map_err(|gave_up| gave_up.error), Err(404));
+        let summary: Vec<_> = trace
+            .iter()
+            .map(|event| (event.attempt, event.outcome.clone()))
+            .collect();
+        let waited = TraceOutcome::Recoverable {
+            error: "Transient(503)".to_string(),
+            decision: WaitDecision::Wait(Duration::from_millis(1)),
+        };
+        assert_eq!(
+            summary,
+            vec![
+                (1, waited.clone()),
+                (2, waited),
+                (
+                    3,
+                    TraceOutcome::Fatal {
+                        error: "404".to_string()
+                    }
+                ),
+            ]
+        );
+        assert!(trace
+            .windows(2)
+            .all(|pair| pair[0].started <= pair[1].started));
+
+        attempts.store(1, Ordering::SeqCst);
+        let z = RetryBuilder::new()
+            .run_traced(
+                one_try,
+                (),
+                None,
+                (&mut (), no_fatal_logger, no_recoverable_logger),
+            )
+            .await;
+        assert_eq!(z.map_err(|gave_up| gave_up.attempts), Err(2));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn paused_gate_holds_retries() {
+        use super::{RetryBuilder, TraceOutcome};
+        use crate::gate::RetryGate;
+        use crate::give_up::{GaveUp, GiveUpReason};
+        use crate::retryable::{RetryableResult, WaitDecision};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which == 0 {
+                    RetryableResult::Retryable(Transient(503))
+                } else {
+                    RetryableResult::GoodResult(which)
+                }
+            }
+        };
+        let no_loggers = (
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Transient, Instant, &mut ())>,
+        );
+
+        let gate = RetryGate::new();
+        gate.pause();
+        let builder = RetryBuilder::new().gate(&gate);
+        let mut context = ();
+        let started = Instant::now();
+        let (z, ()) = tokio::join!(
+            builder.run_detailed(one_try, (), (&mut context, no_loggers.0, no_loggers.1)),
+            async {
+                async_std::task::sleep(Duration::from_millis(20)).await;
+                assert_eq!(attempts.load(Ordering::SeqCst), 1);
+                gate.resume();
+            }
+        );
+        assert_eq!(z, Ok(1));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        attempts.store(0, Ordering::SeqCst);
+        gate.pause();
+        let mut trace = Vec::new();
+        let z = RetryBuilder::new()
+            .gate(&gate)
+            .total_timeout(Duration::from_millis(10))
+            .run_traced(
+                one_try,
+                (),
+                Some(&mut trace),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(
+            z,
+            Err(GaveUp {
+                error: 503,
+                reason: GiveUpReason::DeadlineExceeded,
+                attempts: 1,
+            })
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            trace
+                .iter()
+                .map(|event| event.outcome.clone())
+                .collect::<Vec<_>>(),
+            vec![TraceOutcome::Recoverable {
+                error: "Transient(503)".to_string(),
+                decision: WaitDecision::GiveUp,
+            }]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn attempts_see_their_budget() {
+        use super::{RetryBuilder, RetryContext};
+        use crate::give_up::GiveUpReason;
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        use std::time::{Duration, Instant};
+        let seen = Mutex::new(Vec::new());
+        let one_try = |(), ctx: RetryContext| {
+            seen.lock().unwrap().push((
+                ctx.attempt(),
+                ctx.attempts_remaining(),
+                ctx.time_remaining(),
+            ));
+            async { RetryableResult::<(), _, u16>::Retryable(Transient(503)) }
+        };
+        let no_loggers = (
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Transient, Instant, &mut ())>,
+        );
+        let z = RetryBuilder::new()
+            .max_attempts(2)
+            .total_timeout(Duration::from_secs(60))
+            .run_with_context(one_try, (), (&mut (), no_loggers.0, no_loggers.1))
+            .await;
+        assert_eq!(
+            z.map_err(|e| e.reason),
+            Err(GiveUpReason::AttemptsExhausted)
+        );
+        let seen = seen.into_inner().unwrap();
+        let budgets: Vec<_> = seen
+            .iter()
+            .map(|(attempt, remaining, _)| (*attempt, *remaining))
+            .collect();
+        assert_eq!(budgets, vec![(1, Some(1)), (2, Some(0))]);
+        assert!(seen
+            .iter()
+            .all(|(_, _, time)| time.is_some_and(|left| left <= Duration::from_secs(60))));
+
+        let unbounded = RetryBuilder::new()
+            .run_with_context(
+                |(), ctx: RetryContext| {
+                    let budget = (ctx.attempts_remaining(), ctx.time_remaining());
+                    async move { RetryableResult::<_, Transient, u16>::GoodResult(budget) }
+                },
+                (),
+                (&mut (), no_loggers.0, no_loggers.1),
+            )
+            .await;
+        assert_eq!(unbounded, Ok((None, None)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn failure_window_sees_every_attempt() {
+        use super::RetryBuilder;
+        use crate::backoff::FailureWindow;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::time::Instant;
+        let window = Arc::new(FailureWindow::new(10));
+        let calls = AtomicUsize::new(0);
+        let z = RetryBuilder::new()
+            .failure_window(window.clone())
+            .run(
+                |()| {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if call < 2 {
+                            RetryableResult::Retryable(Transient(503))
+                        } else {
+                            RetryableResult::GoodResult(call)
+                        }
+                    }
+                },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, Instant, &mut ())>,
+                    None::<fn(&Transient, Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Ok(2));
+        assert_eq!(window.failures(), (2, 3));
+
+        let z = RetryBuilder::new()
+            .failure_window(window.clone())
+            .run(
+                |()| async { RetryableResult::<(), Transient, u16>::Fatal(400) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u16, Instant, &mut ())>,
+                    None::<fn(&Transient, Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(400));
+        assert_eq!(window.failures(), (2, 3));
+    }
+}
@@ -0,0 +1,215 @@
+//! retrying database queries made with `sqlx`
+//! serialization failures and deadlocks are worth another attempt
+//!     as are a pool with no free connection and a connection that dropped
+//! constraint violations and everything else are fatal
+//! which SQLSTATEs count as recoverable is up to the caller
+//!     since drivers do not agree on them
+
+use crate::backoff::Backoff;
+use crate::try_again::retry_if;
+use sqlx::error::ErrorKind;
+use std::{borrow::Cow, future::Future};
+
+/// `40001` is `serialization_failure` and is also what MySQL gives for a deadlock
+/// `40P01` is Postgres's `deadlock_detected`
+pub const DEFAULT_RECOVERABLE_SQLSTATES: [&str; 2] = ["40001", "40P01"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlxClassifier {
+    recoverable_sqlstates: Vec<Cow<'static, str>>,
+}
+
+impl Default for SqlxClassifier {
+    fn default() -> Self {
+        Self {
+            recoverable_sqlstates: DEFAULT_RECOVERABLE_SQLSTATES
+                .into_iter()
+                .map(Cow::Borrowed)
+                .collect(),
+        }
+    }
+}
+
+impl SqlxClassifier {
+    #[must_use]
+    pub fn new() -> Self {
+        //! recovers on `DEFAULT_RECOVERABLE_SQLSTATES`
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_sqlstates<I, C>(sqlstates: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<Cow<'static, str>>,
+    {
+        //! recovers on exactly these SQLSTATEs instead of the defaults
+        Self {
+            recoverable_sqlstates: sqlstates.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn recover_on(mut self, sqlstate: impl Into<Cow<'static, str>>) -> Self {
+        //! one more SQLSTATE to treat as recoverable
+        self.recoverable_sqlstates.push(sqlstate.into());
+        self
+    }
+
+    #[must_use]
+    pub fn is_recoverable(&self, error: &sqlx::Error) -> bool {
+        //! a constraint violation is never recoverable, even if its SQLSTATE was listed
+        match error {
+            sqlx::Error::Database(db) => {
+                matches!(db.kind(), ErrorKind::Other)
+                    && db
+                        .code()
+                        .is_some_and(|code| self.recoverable_sqlstates.contains(&code))
+            }
+            sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => true,
+            _ => false,
+        }
+    }
+}
+
+pub async fn retry_query<SuccessType, QueryFun, Fut0, BackoffType>(
+    query: QueryFun,
+    classifier: &SqlxClassifier,
+    backoff: BackoffType,
+) -> Result<SuccessType, sqlx::Error>
+where
+    QueryFun: Fn() -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, sqlx::Error>>,
+    BackoffType: Backoff,
+{
+    //! `retry_if` with `classifier` deciding which errors to retry
+    //! `query` makes a fresh query future each attempt
+    //!     such as `|| sqlx::query("...").execute(&pool)`
+    //!     and anything it does inside a transaction should start that transaction itself
+    //!     since a serialization failure rolls the whole transaction back
+    //! # Errors
+    //! the last error, when it was fatal or `backoff` said to give up
+    retry_if(|()| query(), (), |e| classifier.is_recoverable(e), backoff).await
+}
+
+mod test {
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct FakeDbError {
+        code: &'static str,
+        kind: sqlx::error::ErrorKind,
+    }
+
+    impl std::fmt::Display for FakeDbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "SQLSTATE {}", self.code)
+        }
+    }
+
+    impl std::error::Error for FakeDbError {}
+
+    impl sqlx::error::DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            self.code
+        }
+
+        fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+            Some(self.code.into())
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> sqlx::error::ErrorKind {
+            match self.kind {
+                sqlx::error::ErrorKind::UniqueViolation => sqlx::error::ErrorKind::UniqueViolation,
+                _ => sqlx::error::ErrorKind::Other,
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    fn db_error(code: &'static str, kind: sqlx::error::ErrorKind) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(FakeDbError { code, kind }))
+    }
+
+    #[test]
+    fn classifies_by_sqlstate() {
+        use super::SqlxClassifier;
+        use sqlx::error::ErrorKind;
+
+        let default = SqlxClassifier::new();
+        assert!(default.is_recoverable(&db_error("40001", ErrorKind::Other)));
+        assert!(default.is_recoverable(&db_error("40P01", ErrorKind::Other)));
+        assert!(!default.is_recoverable(&db_error("55P03", ErrorKind::Other)));
+        assert!(!default.is_recoverable(&db_error("40001", ErrorKind::UniqueViolation)));
+        assert!(default.is_recoverable(&sqlx::Error::PoolTimedOut));
+        assert!(
+            default.is_recoverable(&sqlx::Error::Io(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset
+            )))
+        );
+        assert!(!default.is_recoverable(&sqlx::Error::RowNotFound));
+
+        let lock_timeouts = default.clone().recover_on("55P03");
+        assert!(lock_timeouts.is_recoverable(&db_error("55P03", ErrorKind::Other)));
+        assert!(lock_timeouts.is_recoverable(&db_error("40001", ErrorKind::Other)));
+
+        let only = SqlxClassifier::with_sqlstates(["55P03"]);
+        assert!(only.is_recoverable(&db_error("55P03", ErrorKind::Other)));
+        assert!(!only.is_recoverable(&db_error("40001", ErrorKind::Other)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn query_retried_until_it_commits() {
+        use super::{retry_query, SqlxClassifier};
+        use crate::backoff::ExponentialBackoff;
+        use sqlx::error::ErrorKind;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let backoff = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            multiplier: 2,
+            max_wait: Duration::from_millis(5),
+            max_attempts: 5,
+        };
+        let attempts = AtomicUsize::new(0);
+        let z = retry_query(
+            || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(db_error("40001", ErrorKind::Other)),
+                    1 => Err(sqlx::Error::PoolTimedOut),
+                    _ => Ok(7),
+                }
+            },
+            &SqlxClassifier::new(),
+            backoff,
+        )
+        .await;
+        assert_eq!(z.ok(), Some(7));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        attempts.store(0, Ordering::SeqCst);
+        let z = retry_query(
+            || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(db_error("23505", ErrorKind::UniqueViolation))
+            },
+            &SqlxClassifier::new(),
+            backoff,
+        )
+        .await;
+        assert!(matches!(z, Err(sqlx::Error::Database(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
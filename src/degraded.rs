@@ -0,0 +1,165 @@
+//! successes that were not quite what was asked for, such as an answer served from a stale cache
+//! this is a success type to put inside `GoodResult` rather than a fourth variant of `RetryableResult`
+//!     so that every existing `match` on a `RetryableResult` keeps compiling
+//!     and callers who never degrade do not have to think about it
+//! a degraded success still ends the retry loop, it is not worth another attempt
+
+use crate::retryable::{Retryable, RetryableResult};
+use crate::try_again::repeatedly_try;
+use std::{future::Future, time::Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Degradable<T, W> {
+    Pristine(T),
+    /// the value is usable but `W` says what was wrong with it
+    Degraded(T, W),
+}
+
+impl<T, W> Degradable<T, W> {
+    pub fn is_degraded(&self) -> bool {
+        matches!(self, Degradable::Degraded(_, _))
+    }
+
+    pub fn warning(&self) -> Option<&W> {
+        match self {
+            Degradable::Pristine(_) => None,
+            Degradable::Degraded(_, w) => Some(w),
+        }
+    }
+
+    pub fn into_value(self) -> T {
+        //! the value either way, dropping any warning
+        match self {
+            Degradable::Pristine(t) | Degradable::Degraded(t, _) => t,
+        }
+    }
+
+    pub fn into_parts(self) -> (T, Option<W>) {
+        match self {
+            Degradable::Pristine(t) => (t, None),
+            Degradable::Degraded(t, w) => (t, Some(w)),
+        }
+    }
+}
+
+pub async fn repeatedly_try_degradable<
+    SuccessType,
+    Warning,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    OnDegraded,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    on_degraded: OnDegraded,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<
+        Output = RetryableResult<Degradable<SuccessType, Warning>, RecoverableErr, FatalErr>,
+    >,
+    OnDegraded: FnOnce(&Warning),
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try` for a `do_this_function` whose successes may be `Degraded`
+    //! a degraded success is still `Ok` with just the value
+    //!     and its warning goes to `on_degraded` first, such as to count how often the cache was stale
+    //! use `repeatedly_try` directly to get the whole `Degradable` back instead
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let (value, warning) = repeatedly_try(do_this_function, arg, loggers)
+        .await?
+        .into_parts();
+    if let Some(warning) = warning {
+        on_degraded(&warning);
+    }
+    Ok(value)
+}
+
+mod test {
+    #[allow(dead_code)]
+    struct Unreachable;
+
+    impl crate::retryable::Retryable for Unreachable {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[test]
+    fn parts_of_a_degraded_success() {
+        use super::Degradable;
+        let fresh = Degradable::<u8, &str>::Pristine(1);
+        assert!(!fresh.is_degraded());
+        assert_eq!(fresh.warning(), None);
+        assert_eq!(fresh.into_parts(), (1, None));
+        let stale = Degradable::Degraded(2, "stale cache");
+        assert!(stale.is_degraded());
+        assert_eq!(stale.warning(), Some(&"stale cache"));
+        assert_eq!(stale.into_value(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn warning_goes_to_the_hook() {
+        use super::{repeatedly_try_degradable, Degradable};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |from_cache: bool| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match (which, from_cache) {
+                    (0, _) => RetryableResult::Retryable(Unreachable),
+                    (_, true) => RetryableResult::GoodResult(Degradable::Degraded(5, "stale")),
+                    (_, false) => RetryableResult::GoodResult(Degradable::Pristine(7)),
+                }
+            }
+        };
+        let no_fatal_logger = None::<fn(&(), std::time::Instant, &mut ())>;
+        let no_recoverable_logger = None::<fn(&Unreachable, std::time::Instant, &mut ())>;
+
+        let mut warnings = Vec::new();
+        let z = repeatedly_try_degradable(
+            one_try,
+            true,
+            |w: &&str| warnings.push(*w),
+            (&mut (), no_fatal_logger, no_recoverable_logger),
+        )
+        .await;
+        assert_eq!(z, Ok(5));
+        assert_eq!(warnings, vec!["stale"]);
+
+        attempts.store(0, Ordering::SeqCst);
+        let z = repeatedly_try_degradable(
+            one_try,
+            false,
+            |w: &&str| warnings.push(*w),
+            (&mut (), no_fatal_logger, no_recoverable_logger),
+        )
+        .await;
+        assert_eq!(z, Ok(7));
+        assert_eq!(warnings, vec!["stale"]);
+    }
+}
@@ -0,0 +1,181 @@
+//! downloading with `repeatedly_try` so that a recoverable failure part way through
+//!     picks up from the last byte received instead of starting over
+//! each attempt opens a reader at a byte offset, such as with an HTTP `Range: bytes={offset}-` request
+//!     and reads it to the end
+
+use crate::retryable::{RetryNotGood, Retryable, RetryableResult};
+use crate::try_again::repeatedly_try;
+use async_std::io::{Read, ReadExt};
+use std::{cell::RefCell, future::Future, time::Instant};
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+#[allow(clippy::module_name_repetitions)]
+pub async fn retry_download<
+    Reader,
+    RecoverableErr,
+    FatalErr,
+    OpenFun,
+    FailLogContext,
+    OpenFut,
+    ClassifyFun,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    open_at: OpenFun,
+    classify: ClassifyFun,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<Vec<u8>, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    OpenFun: Fn(u64) -> OpenFut,
+    OpenFut: Future<Output = RetryableResult<Reader, RecoverableErr, FatalErr>>,
+    Reader: Read + Unpin,
+    ClassifyFun: Fn(std::io::Error, u64) -> RetryNotGood<RecoverableErr, FatalErr>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the whole body, once some attempt reads its reader all the way to the end
+    //! `open_at` is given how many bytes have been received so far
+    //!     and has to give a reader that starts at exactly that offset
+    //!     so a server that ignores the range and sends the whole body again should be a fatal error
+    //! `classify` decides if an error while reading is recoverable
+    //!     it is also given the offset that was reached so the error can carry it
+    //! the bytes from an attempt that failed part way are kept
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let received = RefCell::new(Vec::new());
+    let one_try = |()| async {
+        let offset = received.borrow().len() as u64;
+        let mut reader = match open_at(offset).await {
+            RetryableResult::GoodResult(reader) => reader,
+            RetryableResult::Retryable(r) => return RetryableResult::Retryable(r),
+            RetryableResult::Fatal(f) => return RetryableResult::Fatal(f),
+        };
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            match reader.read(&mut chunk).await {
+                Ok(0) => return RetryableResult::GoodResult(()),
+                Ok(n) => received.borrow_mut().extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let offset = received.borrow().len() as u64;
+                    return match classify(e, offset) {
+                        RetryNotGood::Retryable(r) => RetryableResult::Retryable(r),
+                        RetryNotGood::Fatal(f) => RetryableResult::Fatal(f),
+                    };
+                }
+            }
+        }
+    };
+    repeatedly_try(one_try, (), loggers).await?;
+    Ok(received.into_inner())
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct ConnectionReset {
+        at: u64,
+    }
+
+    impl Retryable for ConnectionReset {
+        type FatalError = String;
+
+        fn to_fatal(self) -> Self::FatalError {
+            format!("connection reset at byte {}", self.at)
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 5).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    /// serves `remaining` a few bytes at a time, failing once `fail_after` bytes have been read
+    #[allow(dead_code)]
+    struct FlakyReader {
+        remaining: &'static [u8],
+        fail_after: Option<usize>,
+    }
+
+    impl async_std::io::Read for FlakyReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut [u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let allowed = self.fail_after.unwrap_or(usize::MAX);
+            if allowed == 0 {
+                return std::task::Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()));
+            }
+            let n = buf.len().min(self.remaining.len()).min(allowed).min(3);
+            buf[..n].copy_from_slice(&self.remaining[..n]);
+            self.remaining = &self.remaining[n..];
+            self.fail_after = self.fail_after.map(|a| a - n);
+            std::task::Poll::Ready(Ok(n))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn resumes_from_offset() {
+        use super::retry_download;
+        use crate::retryable::{RetryNotGood, RetryableResult};
+        use std::sync::Mutex;
+        const BODY: &[u8] = b"the quick brown fox jumps over the lazy dog";
+        let opened_at = Mutex::new(Vec::new());
+        let open_at = |offset: u64| {
+            let mut opened_at = opened_at.lock().unwrap();
+            let fail_after = (opened_at.len() < 3).then_some(10);
+            opened_at.push(offset);
+            async move {
+                RetryableResult::<_, ConnectionReset, String>::GoodResult(FlakyReader {
+                    remaining: &BODY[usize::try_from(offset).unwrap()..],
+                    fail_after,
+                })
+            }
+        };
+        let classify = |e: std::io::Error, at| {
+            assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset);
+            RetryNotGood::Retryable(ConnectionReset { at })
+        };
+        let z = retry_download(
+            open_at,
+            classify,
+            (
+                &mut (),
+                None::<fn(&String, std::time::Instant, &mut ())>,
+                None::<fn(&ConnectionReset, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z.as_deref(), Ok(BODY));
+        assert_eq!(*opened_at.lock().unwrap(), vec![0, 10, 20, 30]);
+
+        let z = retry_download(
+            |offset| async move {
+                RetryableResult::<_, ConnectionReset, String>::GoodResult(FlakyReader {
+                    remaining: &BODY[usize::try_from(offset).unwrap()..],
+                    fail_after: Some(4),
+                })
+            },
+            |_, at| RetryNotGood::Fatal(format!("gave up at {at}")),
+            (
+                &mut (),
+                None::<fn(&String, std::time::Instant, &mut ())>,
+                None::<fn(&ConnectionReset, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("gave up at 4".to_string()));
+    }
+}
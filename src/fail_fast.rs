@@ -0,0 +1,219 @@
+//! a short lived negative cache in front of a retry loop
+//! when a loop for some key has just given up, identical calls for the same key
+//!     get the same fatal error right away for a while instead of each running the whole loop again
+//!     so a burst of requests for something that is down does not multiply into a burst of retries
+//! this is per key, a finer grain than backing off from a whole dependency
+
+use crate::retryable::{Retryable, RetryableResult};
+use crate::try_again::repeatedly_try;
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[allow(clippy::module_name_repetitions)]
+pub struct FailFastCache<K, F> {
+    ttl: Duration,
+    failures: Mutex<HashMap<K, (F, Instant)>>,
+}
+
+impl<K, F> FailFastCache<K, F>
+where
+    K: Eq + Hash,
+{
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        //! a failure is remembered for `ttl` after the loop gave up
+        Self {
+            ttl,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<F>
+    where
+        F: Clone,
+    {
+        //! the fatal error the last loop for `key` gave up with, if that was less than `ttl` ago
+        let now = Instant::now();
+        let mut failures = self
+            .failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match failures.get(key) {
+            Some((error, expires)) if *expires > now => Some(error.clone()),
+            Some(_) => {
+                failures.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn record(&self, key: K, error: F) {
+        //! remember that the loop for `key` just gave up with `error`
+        //! failures for other keys that have expired are dropped at the same time
+        //!     so keys that are never asked for again do not pile up
+        let now = Instant::now();
+        let mut failures = self
+            .failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        failures.retain(|_, (_, expires)| *expires > now);
+        if let Some(expires) = now.checked_add(self.ttl) {
+            failures.insert(key, (error, expires));
+        }
+    }
+
+    pub fn forget(&self, key: &K) {
+        //! let the next call for `key` run the loop again even if the failure has not expired
+        //!     such as once something else has seen that the dependency is back
+        self.failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(key);
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub async fn repeatedly_try_fail_fast<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    Key,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    cache: &FailFastCache<Key, FatalErr>,
+    key: Key,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    FatalErr: Clone,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    Key: Eq + Hash,
+{
+    //! `repeatedly_try` unless `cache` has a recent failure for `key`
+    //!     in which case that fatal error is given back without calling `do_this_function` at all
+    //!     and without calling the loggers, since nothing new failed
+    //! when the loop gives up its fatal error goes into `cache` for `key`
+    //!     and when it succeeds any failure still in `cache` for `key` is forgotten
+    //! `key` should say which calls are identical, such as the request it was made from
+    //! # Errors
+    //! the cached fatal error, or when there are too many recoverable errors to a level of a breaking point
+    //!     or one of the steps gave a `FatalErr` directly
+    if let Some(recent) = cache.get(&key) {
+        return Err(recent);
+    }
+    let outcome = repeatedly_try(do_this_function, arg, loggers).await;
+    match &outcome {
+        Ok(_) => cache.forget(&key),
+        Err(f) => cache.record(key, f.clone()),
+    }
+    outcome
+}
+
+mod test {
+    #[allow(dead_code)]
+    struct Down;
+
+    impl crate::retryable::Retryable for Down {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "down"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn identical_calls_fail_fast() {
+        use super::{repeatedly_try_fail_fast, FailFastCache};
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { RetryableResult::<(), _, &'static str>::Retryable(Down) }
+        };
+        let no_fatal_logger = None::<fn(&&'static str, std::time::Instant, &mut ())>;
+        let no_recoverable_logger = None::<fn(&Down, std::time::Instant, &mut ())>;
+
+        let cache = FailFastCache::new(Duration::from_secs(60));
+        for _ in 0..3 {
+            let z = repeatedly_try_fail_fast(
+                one_try,
+                (),
+                &cache,
+                "GET /a",
+                (&mut (), no_fatal_logger, no_recoverable_logger),
+            )
+            .await;
+            assert_eq!(z, Err("down"));
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let z = repeatedly_try_fail_fast(
+            one_try,
+            (),
+            &cache,
+            "GET /b",
+            (&mut (), no_fatal_logger, no_recoverable_logger),
+        )
+        .await;
+        assert_eq!(z, Err("down"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 6);
+
+        cache.forget(&"GET /a");
+        let z = repeatedly_try_fail_fast(
+            |()| async { RetryableResult::<_, Down, _>::GoodResult(1) },
+            (),
+            &cache,
+            "GET /a",
+            (&mut (), no_fatal_logger, no_recoverable_logger),
+        )
+        .await;
+        assert_eq!(z, Ok(1));
+        assert_eq!(cache.get(&"GET /a"), None);
+    }
+
+    #[test]
+    fn failures_expire() {
+        use super::FailFastCache;
+        use std::time::Duration;
+        let expired = FailFastCache::new(Duration::ZERO);
+        expired.record(1, "down");
+        assert_eq!(expired.get(&1), None);
+
+        let remembered = FailFastCache::new(Duration::from_secs(60));
+        remembered.record(1, "down");
+        assert_eq!(remembered.get(&1), Some("down"));
+        assert_eq!(remembered.get(&2), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! a retry loop as a `FusedFuture` for use in a `futures::select!` loop
+//!     which needs to know that a branch has already finished so it does not poll it again
+//! the future is boxed so it is also `Unpin` and can go straight into `select!`
+
+use futures_core::future::FusedFuture;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub struct RetryFuture<Fut>
+where
+    Fut: Future,
+{
+    inner: Option<Pin<Box<Fut>>>,
+}
+
+impl<Fut> RetryFuture<Fut>
+where
+    Fut: Future,
+{
+    pub fn new(retry_loop: Fut) -> Self {
+        //! such as `RetryFuture::new(repeatedly_try(do_this_function, arg, loggers))`
+        Self {
+            inner: Some(Box::pin(retry_loop)),
+        }
+    }
+}
+
+impl<Fut> Future for RetryFuture<Fut>
+where
+    Fut: Future,
+{
+    type Output = Fut::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        //! once it has given its output, polling again is `Pending` forever instead of a panic
+        let Some(inner) = self.inner.as_mut() else {
+            return Poll::Pending;
+        };
+        let output = inner.as_mut().poll(cx);
+        if output.is_ready() {
+            self.inner = None;
+        }
+        output
+    }
+}
+
+impl<Fut> FusedFuture for RetryFuture<Fut>
+where
+    Fut: Future,
+{
+    fn is_terminated(&self) -> bool {
+        self.inner.is_none()
+    }
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    struct Again;
+
+    impl Retryable for Again {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::ZERO)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn terminated_after_output() {
+        use super::RetryFuture;
+        use crate::retryable::RetryableResult;
+        use crate::try_again::repeatedly_try;
+        use futures_core::future::FusedFuture;
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+        let (mut first_ctx, mut second_ctx) = ((), ());
+        let mut fut = RetryFuture::new(repeatedly_try(
+            |()| async { RetryableResult::<u8, Again, ()>::GoodResult(3) },
+            (),
+            (
+                &mut first_ctx,
+                None::<fn(&(), std::time::Instant, &mut ())>,
+                None::<fn(&Again, std::time::Instant, &mut ())>,
+            ),
+        ));
+        assert!(!fut.is_terminated());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(
+            std::pin::Pin::new(&mut fut).poll(&mut cx),
+            Poll::Ready(Ok(3))
+        );
+        assert!(fut.is_terminated());
+        assert_eq!(std::pin::Pin::new(&mut fut).poll(&mut cx), Poll::Pending);
+
+        let gives_up = RetryFuture::new(repeatedly_try(
+            |()| async { RetryableResult::<u8, _, ()>::Retryable(Again) },
+            (),
+            (
+                &mut second_ctx,
+                None::<fn(&(), std::time::Instant, &mut ())>,
+                None::<fn(&Again, std::time::Instant, &mut ())>,
+            ),
+        ));
+        assert_eq!(gives_up.await, Err(()));
+    }
+}
@@ -0,0 +1,102 @@
+//! a switch for holding back retries everywhere at once
+//!     such as during a known maintenance window of a dependency that is down on purpose
+//! operators flip it, for example from an admin endpoint, with `pause` and `resume`
+//! it is opt in per call with `RetryBuilder::gate`, a loop without a gate never looks at one
+//! only retries wait for the gate, the first attempt of every call goes ahead as usual
+
+use std::{
+    future::{poll_fn, Future},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    task::{Poll, Waker},
+};
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default)]
+pub struct RetryGate {
+    paused: AtomicBool,
+    waiting: Mutex<Vec<Waker>>,
+}
+
+static GLOBAL_GATE: RetryGate = RetryGate::new();
+
+impl RetryGate {
+    #[must_use]
+    pub const fn new() -> Self {
+        //! starts open
+        Self {
+            paused: AtomicBool::new(false),
+            waiting: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn global() -> &'static Self {
+        //! the one process wide gate, for when every loop should share a switch
+        &GLOBAL_GATE
+    }
+
+    pub fn pause(&self) {
+        //! retries that have not started yet wait until `resume`
+        //!     attempts that are already running are left alone
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        //! lets every waiting retry go ahead
+        self.paused.store(false, Ordering::SeqCst);
+        let waiting = std::mem::take(
+            &mut *self
+                .waiting
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+        for waker in waiting {
+            waker.wake();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn until_open(&self) -> impl Future<Output = ()> + '_ {
+        //! ready right away when the gate is open, otherwise once it is resumed
+        //! dropping it stops waiting, so it can be raced against a deadline or cancelled
+        poll_fn(|cx| {
+            let mut waiting = self
+                .waiting
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if !self.is_paused() {
+                return Poll::Ready(());
+            }
+            if !waiting.iter().any(|w| w.will_wake(cx.waker())) {
+                waiting.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+}
+
+mod test {
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn paused_gate_waits_for_resume() {
+        use super::RetryGate;
+        use std::time::{Duration, Instant};
+        let gate = RetryGate::new();
+        gate.until_open().await;
+
+        gate.pause();
+        assert!(gate.is_paused());
+        let started = Instant::now();
+        tokio::join!(gate.until_open(), async {
+            async_std::task::sleep(Duration::from_millis(20)).await;
+            gate.resume();
+        });
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert!(!gate.is_paused());
+    }
+}
@@ -0,0 +1,422 @@
+//! tail latency mitigation by running more than one attempt at a time
+//! one attempt starts right away and if there is no answer after a while another one starts
+//!     alongside it, and so on, up to a cap on how many run at once
+//! the first success wins and the attempts still running are cancelled by being dropped
+
+use crate::backoff::Backoff;
+use crate::retryable::{Retryable, RetryableResult, WaitDecision};
+use crate::sleep::{global_sleep, plausible, LoopClock};
+use crate::try_again::{as_attempt, log_and_give_up};
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+type Tick = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+enum HedgeEvent<Out> {
+    Finished(Out),
+    Tick,
+}
+
+fn tick_after(how_long: Duration) -> Tick {
+    global_sleep(how_long)
+}
+
+#[allow(clippy::too_many_lines)]
+pub async fn repeatedly_try_expanding_hedge<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    HedgeType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    hedge: &HedgeType,
+    max_concurrency: usize,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    HedgeType: Backoff,
+{
+    //! `hedge` says how long after the latest attempt started to start another one
+    //!     given when all of the attempts so far started, and None to stop adding more
+    //!     so an `ExponentialBackoff` also caps how many attempts there are in total
+    //!     since once it has said None no attempt starts again, not even after a recoverable error
+    //! no more than `max_concurrency` attempts run at once, and at least one always can
+    //! `current_attempt` in each attempt is the order it was started in, 1 for the first one
+    //! an attempt that fails recoverably frees up its slot
+    //!     if it was the only one running, the next attempt starts after its `wait_decision`
+    //!     if that says to give up, or `hedge` already said None, no more attempts start
+    //!         and it gives up once the ones still running have failed too
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let max_concurrency = max_concurrency.max(1);
+    let mut my_retriable_failures = Vec::<(RecoverableErr, Instant)>::new();
+    let launch = |attempt| Box::pin(as_attempt(attempt, || do_this_function(arg.clone())));
+    let mut launches = vec![((), Instant::now())];
+    let mut in_flight = vec![launch(1)];
+    let mut next_launch = hedge
+        .next_delay(Instant::now(), &launches)
+        .map(|hedge_after| tick_after(plausible(hedge_after)));
+    let mut hedge_stopped = next_launch.is_none();
+    let mut exhausted = false;
+    let mut clock = LoopClock::default();
+    loop {
+        let event = poll_fn(|cx| {
+            for which in 0..in_flight.len() {
+                if let Poll::Ready(out) = in_flight[which].as_mut().poll(cx) {
+                    drop(in_flight.swap_remove(which));
+                    return Poll::Ready(HedgeEvent::Finished(out));
+                }
+            }
+            if let Some(tick) = next_launch.as_mut() {
+                if tick.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(HedgeEvent::Tick);
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        match event {
+            HedgeEvent::Tick => {
+                if in_flight.len() < max_concurrency {
+                    launches.push(((), Instant::now()));
+                    in_flight.push(launch(launches.len()));
+                }
+                next_launch = hedge
+                    .next_delay(Instant::now(), &launches)
+                    .map(|hedge_after| tick_after(plausible(hedge_after)));
+                hedge_stopped = next_launch.is_none();
+            }
+            HedgeEvent::Finished(RetryableResult::GoodResult(z)) => {
+                return Ok(z);
+            }
+            HedgeEvent::Finished(RetryableResult::Retryable(r)) => {
+                let this_time = clock.now();
+                if r.resets_history() {
+                    my_retriable_failures.clear();
+                }
+                let decision = r.wait_decision(this_time, &my_retriable_failures);
+                exhausted |= decision == WaitDecision::GiveUp;
+                if (exhausted || hedge_stopped) && in_flight.is_empty() {
+                    let f = r.escalate(&my_retriable_failures);
+                    return Err(log_and_give_up(
+                        loggers,
+                        &my_retriable_failures,
+                        f,
+                        this_time,
+                    ));
+                }
+                my_retriable_failures.push((r, this_time));
+                if exhausted || hedge_stopped {
+                    next_launch = None;
+                } else if in_flight.is_empty() {
+                    let how_long_to_wait = match decision {
+                        WaitDecision::Wait(how_long_to_wait) => plausible(how_long_to_wait),
+                        WaitDecision::Immediate | WaitDecision::GiveUp => Duration::ZERO,
+                    };
+                    next_launch = Some(if clock.skip(how_long_to_wait) {
+                        Box::pin(std::future::ready(()))
+                    } else {
+                        tick_after(how_long_to_wait)
+                    });
+                }
+            }
+            HedgeEvent::Finished(RetryableResult::Fatal(f)) => {
+                let this_time = clock.now();
+                return Err(log_and_give_up(
+                    loggers,
+                    &my_retriable_failures,
+                    f,
+                    this_time,
+                ));
+            }
+        }
+    }
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    struct Overloaded;
+
+    impl Retryable for Overloaded {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "overloaded"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    /// counts how many attempts are running and notices being dropped before finishing
+    #[allow(dead_code)]
+    struct Running<'a> {
+        now: &'a std::sync::atomic::AtomicUsize,
+        cancelled: &'a std::sync::atomic::AtomicUsize,
+        finished: bool,
+    }
+
+    impl Drop for Running<'_> {
+        fn drop(&mut self) {
+            use std::sync::atomic::Ordering;
+            self.now.fetch_sub(1, Ordering::SeqCst);
+            if !self.finished {
+                self.cancelled.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn first_success_wins_and_losers_are_cancelled() {
+        use super::repeatedly_try_expanding_hedge;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let (started, now, most, cancelled) = (
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        );
+        let one_try = |()| {
+            let which = started.fetch_add(1, Ordering::SeqCst);
+            let running = now.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(running, Ordering::SeqCst);
+            let guard = Running {
+                now: &now,
+                cancelled: &cancelled,
+                finished: false,
+            };
+            async move {
+                let mut guard = guard;
+                let latency = if which == 3 { 5 } else { 500 };
+                async_std::task::sleep(Duration::from_millis(latency)).await;
+                guard.finished = true;
+                RetryableResult::<_, Overloaded, _>::GoodResult(which)
+            }
+        };
+        let hedge = ExponentialBackoff {
+            base: Duration::from_millis(10),
+            multiplier: 1,
+            max_wait: Duration::from_millis(10),
+            max_attempts: 10,
+        };
+        let z = repeatedly_try_expanding_hedge(
+            one_try,
+            (),
+            &hedge,
+            4,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Overloaded, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(3));
+        assert_eq!(started.load(Ordering::SeqCst), 4);
+        assert_eq!(most.load(Ordering::SeqCst), 4);
+        assert_eq!(cancelled.load(Ordering::SeqCst), 3);
+        assert_eq!(now.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn gives_up_after_running_attempts_fail() {
+        use super::repeatedly_try_expanding_hedge;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let started = AtomicUsize::new(0);
+        let one_try = |()| {
+            started.fetch_add(1, Ordering::SeqCst);
+            async {
+                async_std::task::sleep(Duration::from_millis(3)).await;
+                RetryableResult::<(), _, _>::Retryable(Overloaded)
+            }
+        };
+        let hedge = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            multiplier: 1,
+            max_wait: Duration::from_millis(1),
+            max_attempts: 100,
+        };
+        let z = repeatedly_try_expanding_hedge(
+            one_try,
+            (),
+            &hedge,
+            2,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Overloaded, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("overloaded"));
+        assert!((4..=5).contains(&started.load(Ordering::SeqCst)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hedged_attempts_know_their_number() {
+        use super::repeatedly_try_expanding_hedge;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use crate::try_again::current_attempt;
+        use std::time::Duration;
+        let one_try = |()| async {
+            match current_attempt() {
+                Some(1) => RetryableResult::Retryable(Overloaded),
+                attempt => RetryableResult::GoodResult(attempt),
+            }
+        };
+        let hedge = ExponentialBackoff {
+            base: Duration::from_secs(60),
+            ..ExponentialBackoff::DEFAULT
+        };
+        let z = repeatedly_try_expanding_hedge(
+            one_try,
+            (),
+            &hedge,
+            2,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Overloaded, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(Some(2)));
+    }
+
+    #[allow(dead_code)]
+    struct Permissive;
+
+    impl Retryable for Permissive {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "gave up"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 100).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hedge_policy_caps_total_attempts() {
+        use super::repeatedly_try_expanding_hedge;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let started = AtomicUsize::new(0);
+        let one_try = |()| {
+            started.fetch_add(1, Ordering::SeqCst);
+            async { RetryableResult::<(), _, _>::Retryable(Permissive) }
+        };
+        let hedge = ExponentialBackoff {
+            base: Duration::from_secs(60),
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        };
+        let z = repeatedly_try_expanding_hedge(
+            one_try,
+            (),
+            &hedge,
+            2,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Permissive, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("gave up"));
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+    }
+
+    #[allow(dead_code)]
+    struct SlowToRecover;
+
+    impl Retryable for SlowToRecover {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "still down"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::from_secs(10))
+        }
+    }
+
+    #[cfg(all(feature = "test-no-sleep", debug_assertions))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hedged_retry_waits_skipped() {
+        use super::repeatedly_try_expanding_hedge;
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use crate::sleep::skipped_backoff;
+        use std::time::{Duration, Instant};
+        let hedge = ExponentialBackoff {
+            base: Duration::from_secs(60 * 60),
+            ..ExponentialBackoff::DEFAULT
+        };
+        let before = skipped_backoff();
+        let started = Instant::now();
+        let z = repeatedly_try_expanding_hedge(
+            |()| async { RetryableResult::<(), _, _>::Retryable(SlowToRecover) },
+            (),
+            &hedge,
+            2,
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&SlowToRecover, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("still down"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(skipped_backoff() >= before + Duration::from_secs(20));
+    }
+}
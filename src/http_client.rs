@@ -0,0 +1,291 @@
+//! retrying requests made directly with `hyper`
+//! a response that was cut off, a connection that was reset and a timeout are worth another attempt
+//!     since those are the network and not the request
+//!     as is a request that was canceled because its connection went away before it was sent
+//! a response that could not be parsed and everything else are fatal
+//! hyper often wraps the error that matters in another `hyper::Error`
+//!     such as a cut off response coming back as `Canceled` caused by `IncompleteMessage`
+//!     so the whole chain of sources is looked at and the innermost error that says anything decides
+//! the classification can be overridden for particular errors
+//!     such as making a timeout fatal for a request that is not safe to send twice
+
+use crate::backoff::Backoff;
+use crate::try_again::retry_if;
+use std::{error::Error, future::Future, io, sync::Arc};
+
+type Override = (Arc<dyn Fn(&hyper::Error) -> bool + Send + Sync>, bool);
+
+#[derive(Clone, Default)]
+pub struct HyperClassifier {
+    overrides: Vec<Override>,
+}
+
+impl HyperClassifier {
+    #[must_use]
+    pub fn new() -> Self {
+        //! recovers on incomplete messages, connection resets and timeouts
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn recover_when<P>(mut self, matches: P) -> Self
+    where
+        P: Fn(&hyper::Error) -> bool + Send + Sync + 'static,
+    {
+        //! errors that `matches` are recoverable whatever the defaults say
+        //! a later `recover_when` or `fatal_when` that also matches wins over this one
+        self.overrides.push((Arc::new(matches), true));
+        self
+    }
+
+    #[must_use]
+    pub fn fatal_when<P>(mut self, matches: P) -> Self
+    where
+        P: Fn(&hyper::Error) -> bool + Send + Sync + 'static,
+    {
+        //! errors that `matches` are fatal whatever the defaults say
+        //! a later `recover_when` or `fatal_when` that also matches wins over this one
+        self.overrides.push((Arc::new(matches), false));
+        self
+    }
+
+    #[must_use]
+    pub fn is_recoverable(&self, error: &hyper::Error) -> bool {
+        //! the last override that matches `error` if there is one, otherwise the defaults
+        if let Some((_, recoverable)) = self
+            .overrides
+            .iter()
+            .rev()
+            .find(|(matches, _)| matches(error))
+        {
+            return *recoverable;
+        }
+        let mut recoverable = false;
+        let mut cause: Option<&(dyn Error + 'static)> = Some(error);
+        while let Some(this) = cause {
+            if let Some(verdict) = says_recoverable(this) {
+                recoverable = verdict;
+            }
+            cause = this.source();
+        }
+        recoverable
+    }
+}
+
+fn says_recoverable(cause: &(dyn Error + 'static)) -> Option<bool> {
+    //! what one error in the chain says on its own, None when it leaves it to its sources
+    //! a canceled request with no more specific cause never made it out of a dropped connection
+    if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+        return Some(matches!(
+            io_error.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+        ));
+    }
+    let error = cause.downcast_ref::<hyper::Error>()?;
+    if error.is_parse() {
+        Some(false)
+    } else if error.is_incomplete_message()
+        || error.is_timeout()
+        || error.is_closed()
+        || error.is_canceled()
+    {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+pub async fn retry_request<SuccessType, RequestFun, Fut0, BackoffType>(
+    request: RequestFun,
+    classifier: &HyperClassifier,
+    backoff: BackoffType,
+) -> Result<SuccessType, hyper::Error>
+where
+    RequestFun: Fn() -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, hyper::Error>>,
+    BackoffType: Backoff,
+{
+    //! `retry_if` with `classifier` deciding which errors to retry
+    //!     and `ExponentialBackoff::default()` is a reasonable `backoff` to start with
+    //! `request` makes a fresh request future each attempt
+    //!     such as `|| sender.send_request(build_request())`
+    //!     since a `hyper` request is consumed by sending it
+    //! # Errors
+    //! the last error, when it was fatal or `backoff` said to give up
+    retry_if(
+        |()| request(),
+        (),
+        |e| classifier.is_recoverable(e),
+        backoff,
+    )
+    .await
+}
+
+mod test {
+    /// answers every read with `response` once and then the end of the stream
+    ///     or fails every read with `fail_with`
+    /// unless `before_request`, reads wait until the request was written
+    ///     as they would on a real connection
+    #[allow(dead_code)]
+    struct ScriptedIo {
+        response: Option<&'static [u8]>,
+        fail_with: Option<std::io::ErrorKind>,
+        before_request: bool,
+        reader: Option<std::task::Waker>,
+    }
+
+    impl ScriptedIo {
+        #[allow(dead_code)]
+        fn new(response: Option<&'static [u8]>, fail_with: Option<std::io::ErrorKind>) -> Self {
+            Self {
+                response,
+                fail_with,
+                before_request: false,
+                reader: None,
+            }
+        }
+    }
+
+    impl hyper::rt::Read for ScriptedIo {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            mut buf: hyper::rt::ReadBufCursor<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if !self.before_request {
+                self.reader = Some(cx.waker().clone());
+                return std::task::Poll::Pending;
+            }
+            if let Some(kind) = self.fail_with {
+                return std::task::Poll::Ready(Err(kind.into()));
+            }
+            if let Some(response) = self.response.take() {
+                buf.put_slice(response);
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    impl hyper::rt::Write for ScriptedIo {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.before_request = true;
+            if let Some(reader) = self.reader.take() {
+                reader.wake();
+            }
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// only in tests, since the `client` feature of hyper is only turned on for them
+    #[cfg(test)]
+    async fn error_from(io: ScriptedIo) -> hyper::Error {
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(io)
+            .await
+            .expect("handshake does no io");
+        tokio::spawn(connection);
+        let request = http::Request::builder()
+            .uri("/")
+            .body(String::new())
+            .expect("valid request");
+        sender
+            .send_request(request)
+            .await
+            .expect_err("the scripted connection never gives a good response")
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn classifies_real_errors() {
+        use super::HyperClassifier;
+        let cut_off = error_from(ScriptedIo::new(None, None)).await;
+        let garbage = error_from(ScriptedIo::new(Some(b"not http at all\r\n\r\n"), None)).await;
+        let reset = error_from(ScriptedIo::new(
+            None,
+            Some(std::io::ErrorKind::ConnectionReset),
+        ))
+        .await;
+        let refused = error_from(ScriptedIo::new(
+            None,
+            Some(std::io::ErrorKind::PermissionDenied),
+        ))
+        .await;
+
+        let mut cut_off_early = ScriptedIo::new(None, None);
+        cut_off_early.before_request = true;
+        let cut_off_early = error_from(cut_off_early).await;
+        let mut reset_early = ScriptedIo::new(None, Some(std::io::ErrorKind::ConnectionReset));
+        reset_early.before_request = true;
+        let reset_early = error_from(reset_early).await;
+
+        assert!(cut_off_early.is_canceled() && reset_early.is_canceled());
+        let default = HyperClassifier::new();
+        assert!(default.is_recoverable(&cut_off_early));
+        assert!(default.is_recoverable(&reset_early));
+        assert!(default.is_recoverable(&cut_off));
+        assert!(!default.is_recoverable(&garbage));
+        assert!(default.is_recoverable(&reset));
+        assert!(!default.is_recoverable(&refused));
+
+        let overridden = HyperClassifier::new()
+            .recover_when(hyper::Error::is_parse)
+            .fatal_when(hyper::Error::is_incomplete_message);
+        assert!(!overridden.is_recoverable(&cut_off));
+        assert!(overridden.is_recoverable(&garbage));
+        assert!(overridden.is_recoverable(&reset));
+
+        let last_wins = overridden.recover_when(hyper::Error::is_incomplete_message);
+        assert!(last_wins.is_recoverable(&cut_off));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn request_retried_after_reset() {
+        use super::{retry_request, HyperClassifier};
+        use crate::backoff::ExponentialBackoff;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let backoff = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            ..ExponentialBackoff::default()
+        };
+        let attempts = AtomicUsize::new(0);
+        let z = retry_request(
+            || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(error_from(ScriptedIo::new(
+                        None,
+                        Some(std::io::ErrorKind::ConnectionReset),
+                    ))
+                    .await)
+                } else {
+                    Ok("body")
+                }
+            },
+            &HyperClassifier::new(),
+            backoff,
+        )
+        .await;
+        assert_eq!(z.ok(), Some("body"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
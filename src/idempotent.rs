@@ -0,0 +1,109 @@
+//! retrying only what is safe to do more than once
+//! an operation is described by its argument type, such as a `GetUser { id }` request
+//!     and marking that type `Idempotent` is a promise that sending it twice does no harm
+//! `repeatedly_try_idempotent` will not compile for an argument that was not marked
+//!     so a `ChargeCard` request can not end up in a retry loop by accident
+//! `repeatedly_try` and the other drivers do not check for the marker at all
+
+use crate::retryable::{Retryable, RetryableResult};
+use crate::try_again::repeatedly_try;
+use std::{future::Future, time::Instant};
+
+pub trait Idempotent {
+    //! a marker with nothing to implement, `impl Idempotent for GetUser {}`
+    //! there are deliberately no implementations for `()` or other general types
+    //!     since those say nothing about what the operation does
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub async fn repeatedly_try_idempotent<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Idempotent + Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! exactly `repeatedly_try`, for an `arg` whose type has been marked `Idempotent`
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    repeatedly_try(do_this_function, arg, loggers).await
+}
+
+mod test {
+    #[allow(dead_code)]
+    #[derive(Clone)]
+    struct GetUser {
+        id: u32,
+    }
+
+    impl super::Idempotent for GetUser {}
+
+    #[allow(dead_code)]
+    struct Unavailable;
+
+    impl crate::retryable::Retryable for Unavailable {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "unavailable"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn marked_argument_is_retried() {
+        use super::repeatedly_try_idempotent;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let attempts = AtomicUsize::new(0);
+        let z = repeatedly_try_idempotent(
+            |request: GetUser| {
+                let attempts = &attempts;
+                async move {
+                    if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                        RetryableResult::Retryable(Unavailable)
+                    } else {
+                        RetryableResult::GoodResult(request.id)
+                    }
+                }
+            },
+            GetUser { id: 7 },
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&Unavailable, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(7));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
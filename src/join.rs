@@ -0,0 +1,180 @@
+//! retrying several operations of different types at once, all of which have to succeed
+//!     such as connecting to the database, the cache and the queue at startup
+//! each one is its own `repeatedly_try` with its own recoverable error type and its own loggers
+//!     and they run concurrently on the current task
+//! as soon as one of them gives up the others are dropped
+//! this is a macro because a function would need a type parameter for every one of the operations
+
+#[doc(hidden)]
+pub use tokio::try_join as __try_join;
+
+/// `retry_all!((do_this_function, arg, loggers), ...)` awaits all of them
+///     each in parentheses with exactly the arguments `repeatedly_try` would take
+/// it has to be used inside an `async` block or function
+/// it gives `Ok` with a tuple of the successes in the same order
+///     or the fatal error of whichever one gave up first
+/// with just the operations, every one of them needs the same fatal error type
+/// `retry_all!(ErrType; (do_this_function, arg, loggers), ...)` converts each fatal error
+///     into `ErrType` with `Into` first, for operations whose fatal error types differ
+/// the form without a type is matched first, since trying to read an operation as a type is an error
+#[macro_export]
+macro_rules! retry_all {
+    ($(($do_this_function:expr, $arg:expr, $loggers:expr $(,)?)),+ $(,)?) => {
+        $crate::join::__try_join!($(async {
+            $crate::try_again::repeatedly_try($do_this_function, $arg, $loggers).await
+        }),+)
+    };
+    ($err:ty; $(($do_this_function:expr, $arg:expr, $loggers:expr $(,)?)),+ $(,)?) => {
+        $crate::join::__try_join!($(async {
+            $crate::try_again::repeatedly_try($do_this_function, $arg, $loggers)
+                .await
+                .map_err(<$err as ::core::convert::From<_>>::from)
+        }),+)
+    };
+}
+
+mod test {
+    #[allow(dead_code)]
+    struct DbBusy;
+
+    impl crate::retryable::Retryable for DbBusy {
+        type FatalError = String;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "database busy".to_string()
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[allow(dead_code)]
+    struct CacheMiss(u8);
+
+    impl crate::retryable::Retryable for CacheMiss {
+        type FatalError = u8;
+
+        fn to_fatal(self) -> Self::FatalError {
+            self.0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 3).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    #[allow(dead_code)]
+    struct DbBusyAgain;
+
+    impl crate::retryable::Retryable for DbBusyAgain {
+        type FatalError = String;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "still busy".to_string()
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            None
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn all_of_different_types() {
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Instant;
+        let db_attempts = AtomicUsize::new(0);
+        let connect_db = |url: &'static str| {
+            let which = db_attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which < 2 {
+                    RetryableResult::Retryable(DbBusy)
+                } else {
+                    RetryableResult::GoodResult(url.len())
+                }
+            }
+        };
+        let warm_cache = |()| async { RetryableResult::<_, DbBusyAgain, _>::GoodResult("warm") };
+
+        let z = crate::retry_all!(
+            (
+                connect_db,
+                "postgres://db",
+                (
+                    &mut (),
+                    None::<fn(&String, Instant, &mut ())>,
+                    None::<fn(&DbBusy, Instant, &mut ())>,
+                ),
+            ),
+            (
+                warm_cache,
+                (),
+                (
+                    &mut (),
+                    None::<fn(&String, Instant, &mut ())>,
+                    None::<fn(&DbBusyAgain, Instant, &mut ())>,
+                ),
+            ),
+        );
+        assert_eq!(z, Ok((13, "warm")));
+        assert_eq!(db_attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn first_fatal_converted() {
+        use crate::retryable::RetryableResult;
+        use std::time::Instant;
+        #[allow(dead_code)]
+        #[derive(Debug, PartialEq)]
+        enum StartupError {
+            Db(String),
+            Cache(u8),
+        }
+        impl From<String> for StartupError {
+            fn from(e: String) -> Self {
+                StartupError::Db(e)
+            }
+        }
+        impl From<u8> for StartupError {
+            fn from(e: u8) -> Self {
+                StartupError::Cache(e)
+            }
+        }
+
+        let z: Result<((), ()), StartupError> = crate::retry_all!(StartupError;
+            (
+                |()| async { RetryableResult::<(), _, _>::Retryable(CacheMiss(4)) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&u8, Instant, &mut ())>,
+                    None::<fn(&CacheMiss, Instant, &mut ())>,
+                ),
+            ),
+            (
+                |()| async { RetryableResult::<(), _, _>::Retryable(DbBusyAgain) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&String, Instant, &mut ())>,
+                    None::<fn(&DbBusyAgain, Instant, &mut ())>,
+                ),
+            ),
+        );
+        assert_eq!(z, Err(StartupError::Db("still busy".to_string())));
+    }
+}
@@ -1,5 +1,73 @@
-pub mod retryable;
+pub mod batch;
+pub mod blocking;
+pub mod builder;
+#[cfg(feature = "sqlx")]
+pub mod database;
+pub mod degraded;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod fail_fast;
+pub mod fused;
+pub mod gate;
+pub mod hedge;
+#[cfg(feature = "hyper")]
+pub mod http_client;
+pub mod idempotent;
+pub mod join;
+pub mod logging;
+pub mod metrics;
+pub mod prelude;
+pub mod sleep;
+pub mod stream;
 pub mod try_again;
+pub mod weighted;
 
-pub use retryable::{Retryable, RetryableResult};
-pub use try_again::repeatedly_try;
+#[cfg(feature = "boxed-error")]
+pub use retryable_core::boxed;
+pub use retryable_core::{backoff, give_up, retryable, simulate, test_util};
+
+pub use backoff::{
+    next_backoff, next_backoff_with_jitter, scaled_by_severity, Backoff, BudgetedExponential,
+    ConstRetry, ExponentialBackoff, FailureWindow, GiveUpOnRepeat, HotReload, MaxAttempts,
+    RateBasedBackoff, RateLimitReset, RateLimited, RetryConfig,
+};
+pub use batch::{
+    repeatedly_try_batch, repeatedly_try_batch_partial, retry_collect, retry_collect_each,
+    BatchOutcome,
+};
+pub use blocking::{
+    repeatedly_try_blocking, repeatedly_try_blocking_cancellable, repeatedly_try_spawn_blocking,
+    retry_blocking_with_timeout, CancelledOr,
+};
+pub use builder::{RetryBuilder, RetryContext, RetryStats, TraceEvent, TraceOutcome, WaitInfo};
+#[cfg(feature = "sqlx")]
+pub use database::{retry_query, SqlxClassifier, DEFAULT_RECOVERABLE_SQLSTATES};
+pub use degraded::{repeatedly_try_degradable, Degradable};
+#[cfg(feature = "download")]
+pub use download::retry_download;
+pub use fail_fast::{repeatedly_try_fail_fast, FailFastCache};
+pub use fused::RetryFuture;
+pub use gate::RetryGate;
+pub use give_up::{GaveUp, GiveUpReason};
+pub use hedge::repeatedly_try_expanding_hedge;
+#[cfg(feature = "hyper")]
+pub use http_client::{retry_request, HyperClassifier};
+pub use idempotent::{repeatedly_try_idempotent, Idempotent};
+pub use logging::{LevelEscalation, LogLevel, MultiLogger, RetryEvent, RetryEventKind, RingLogger};
+pub use retryable::{
+    relative_history, RetryNotGood, Retryable, RetryableResult, RetryableResultExt, WaitDecision,
+};
+pub use simulate::{RetrySimulation, SIMULATION_LIMIT};
+#[cfg(feature = "wasm")]
+pub use sleep::WasmSleeper;
+pub use sleep::{
+    global_sleeper, set_global_sleeper, skipped_backoff, AsyncStdSleeper, GlobalSleeper, Sleeper,
+    IMPLAUSIBLE_WAIT,
+};
+pub use stream::{retry_try_stream, RetryTryStream};
+pub use try_again::{
+    current_attempt, repeatedly_try, repeatedly_try_classified, repeatedly_try_cow,
+    repeatedly_try_simple, repeatedly_try_with_async_backoff, repeatedly_try_with_async_loggers,
+    repeatedly_try_with_history, repeatedly_try_with_setup, retry_if, AsyncBackoff, DynClassifier,
+};
+pub use weighted::{repeatedly_try_weighted, WeightedTargets};
@@ -0,0 +1,439 @@
+//! helpers for building the loggers that `repeatedly_try` takes
+//! a `MultiLogger` sends each error to one of several sinks
+//!     according to a dispatcher that classifies the error into some target
+//! this avoids one giant match statement inside a single logger
+//!     when a heterogeneous error enum should go to different places
+//! a `RingLogger` keeps the last few errors from every retry loop in memory
+//!     so that a panic hook or crash handler can dump them
+//! a `LevelEscalation` picks a level for each recoverable error by how many came before it
+//!     so the first few blips of a loop stay quiet and a long run of failures gets loud
+
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+type Sink<'a, E, Ctx> = Box<dyn Fn(&E, Instant, &mut Ctx) + 'a>;
+
+pub struct MultiLogger<'a, E, Ctx, Target>
+where
+    Target: PartialEq,
+{
+    dispatcher: Box<dyn Fn(&E) -> Target + 'a>,
+    routes: Vec<(Target, Sink<'a, E, Ctx>)>,
+    fallback: Option<Sink<'a, E, Ctx>>,
+}
+
+impl<'a, E, Ctx, Target> MultiLogger<'a, E, Ctx, Target>
+where
+    Target: PartialEq,
+{
+    pub fn new<D>(dispatcher: D) -> Self
+    where
+        D: Fn(&E) -> Target + 'a,
+    {
+        //! the dispatcher decides which target each error goes to
+        //! the sinks for each target are added with `route`
+        Self {
+            dispatcher: Box::new(dispatcher),
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    #[must_use]
+    pub fn route<L>(mut self, target: Target, sink: L) -> Self
+    where
+        L: Fn(&E, Instant, &mut Ctx) + 'a,
+    {
+        //! errors the dispatcher sends to `target` get passed to `sink`
+        //! if the same target is routed more than once, all of those sinks get the error
+        self.routes.push((target, Box::new(sink)));
+        self
+    }
+
+    #[must_use]
+    pub fn fallback<L>(mut self, sink: L) -> Self
+    where
+        L: Fn(&E, Instant, &mut Ctx) + 'a,
+    {
+        //! errors whose target has no routes go here
+        //! without a fallback they are dropped silently
+        self.fallback = Some(Box::new(sink));
+        self
+    }
+
+    pub fn log(&self, error: &E, time: Instant, ctx: &mut Ctx) {
+        //! has the same shape as the loggers `repeatedly_try` takes
+        let target = (self.dispatcher)(error);
+        let mut any_routed = false;
+        for (_, sink) in self.routes.iter().filter(|(t, _)| *t == target) {
+            sink(error, time, ctx);
+            any_routed = true;
+        }
+        if !any_routed {
+            if let Some(fallback) = &self.fallback {
+                fallback(error, time, ctx);
+            }
+        }
+    }
+
+    pub fn into_logger(self) -> impl Fn(&E, Instant, &mut Ctx) + 'a
+    where
+        E: 'a,
+        Ctx: 'a,
+        Target: 'a,
+    {
+        //! turns this into a closure that can be given directly as one of the loggers
+        move |error, time, ctx| self.log(error, time, ctx)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryEventKind {
+    Recoverable,
+    Fatal,
+}
+
+/// one error that a retry loop gave to a `RingLogger`
+///     the error is kept as its `Debug` text so that errors of every type can share one buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryEvent {
+    pub kind: RetryEventKind,
+    pub at: Instant,
+    pub error: String,
+}
+
+pub struct RingLogger {
+    capacity: usize,
+    events: Mutex<VecDeque<RetryEvent>>,
+}
+
+static GLOBAL_RING: OnceLock<RingLogger> = OnceLock::new();
+
+impl RingLogger {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        //! keeps at most `capacity` events, dropping the oldest one to make room
+        //! a capacity of 0 keeps nothing
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn install_global(capacity: usize) -> &'static Self {
+        //! the one process wide buffer, made with `capacity` the first time this is called
+        //! later calls give back the same buffer and ignore their `capacity`
+        GLOBAL_RING.get_or_init(|| Self::new(capacity))
+    }
+
+    pub fn global() -> Option<&'static Self> {
+        //! the process wide buffer if `install_global` has been called
+        //!     such as from inside a panic hook
+        GLOBAL_RING.get()
+    }
+
+    pub fn record(&self, kind: RetryEventKind, error: &impl Debug, at: Instant) {
+        //! this never panics, even if another thread panicked while recording
+        //!     so that it is safe to use while a crash is already happening
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self
+            .events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(RetryEvent {
+            kind,
+            at,
+            error: format!("{error:?}"),
+        });
+    }
+
+    pub fn recoverable_logger<E: Debug, Ctx>(&self) -> impl Fn(&E, Instant, &mut Ctx) + '_ {
+        //! to give to a retry loop as its logger for recoverable errors
+        //! like every logger it is only called once that loop has given up
+        move |error, at, _| self.record(RetryEventKind::Recoverable, error, at)
+    }
+
+    pub fn fatal_logger<E: Debug, Ctx>(&self) -> impl Fn(&E, Instant, &mut Ctx) + '_ {
+        //! to give to a retry loop as its logger for fatal errors
+        move |error, at, _| self.record(RetryEventKind::Fatal, error, at)
+    }
+
+    pub fn snapshot(&self) -> Vec<RetryEvent> {
+        //! the events still in the buffer, oldest first
+        self.events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// the levels a `LevelEscalation` chooses between
+///     the sink maps them onto whatever logging it uses, such as `log::Level`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+}
+
+/// the level for each recoverable error of a loop, rising with its attempt number
+///     attempts are counted from 1
+///     so `info_from: 3` means the first two recoverable errors are at debug
+/// fatal errors are left to their own logger, they always deserve attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelEscalation {
+    pub info_from: usize,
+    pub warn_from: usize,
+}
+
+impl LevelEscalation {
+    /// debug for the first two attempts, info up to the ninth and warn from the tenth on
+    pub const DEFAULT: Self = Self {
+        info_from: 3,
+        warn_from: 10,
+    };
+
+    #[must_use]
+    pub fn level_for(&self, attempt: usize) -> LogLevel {
+        //! warn wins when `warn_from` is not after `info_from`
+        if attempt >= self.warn_from {
+            LogLevel::Warn
+        } else if attempt >= self.info_from {
+            LogLevel::Info
+        } else {
+            LogLevel::Debug
+        }
+    }
+
+    pub fn recoverable_logger<E, Ctx, L>(self, sink: L) -> impl Fn(&E, Instant, &mut Ctx)
+    where
+        L: Fn(LogLevel, &E, Instant, &mut Ctx),
+    {
+        //! to give to a retry loop as its logger for recoverable errors
+        //!     with `sink` getting the level alongside each error
+        //! the loop passes the recoverable errors in order, so the calls are counted as the attempts
+        //!     which means each loop needs a logger of its own from this
+        let attempts = AtomicUsize::new(0);
+        move |error, at, ctx| {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed).saturating_add(1);
+            sink(self.level_for(attempt), error, at, ctx);
+        }
+    }
+}
+
+impl Default for LevelEscalation {
+    fn default() -> Self {
+        //! `LevelEscalation::DEFAULT`
+        Self::DEFAULT
+    }
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    enum HeterogeneousError {
+        Network(u16),
+        Validation(&'static str),
+        Other,
+    }
+
+    #[allow(dead_code)]
+    #[derive(PartialEq)]
+    enum LogTarget {
+        NetworkSink,
+        ValidationSink,
+        Elsewhere,
+    }
+
+    impl Retryable for HeterogeneousError {
+        type FatalError = ();
+
+        fn to_fatal(self) -> Self::FatalError {}
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            if previous_retriable_failures.len() < 3 {
+                Some(std::time::Duration::from_millis(1))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Default)]
+    struct Sinks {
+        network: Vec<u16>,
+        validation: Vec<&'static str>,
+        unrouted: usize,
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn routes_by_variant() {
+        use super::MultiLogger;
+        use crate::retryable::RetryableResult;
+        use crate::try_again::repeatedly_try;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let counter = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                RetryableResult::<(), _, ()>::Retryable(match which {
+                    0 => HeterogeneousError::Network(503),
+                    1 => HeterogeneousError::Validation("bad field"),
+                    2 => HeterogeneousError::Other,
+                    _ => HeterogeneousError::Network(504),
+                })
+            }
+        };
+        let logger = MultiLogger::new(|e: &HeterogeneousError| match e {
+            HeterogeneousError::Network(_) => LogTarget::NetworkSink,
+            HeterogeneousError::Validation(_) => LogTarget::ValidationSink,
+            HeterogeneousError::Other => LogTarget::Elsewhere,
+        })
+        .route(LogTarget::NetworkSink, |e, _, ctx: &mut Sinks| {
+            if let HeterogeneousError::Network(code) = e {
+                ctx.network.push(*code);
+            }
+        })
+        .route(LogTarget::ValidationSink, |e, _, ctx: &mut Sinks| {
+            if let HeterogeneousError::Validation(why) = e {
+                ctx.validation.push(why);
+            }
+        })
+        .fallback(|_, _, ctx: &mut Sinks| ctx.unrouted += 1)
+        .into_logger();
+
+        let mut sinks = Sinks::default();
+        let z = repeatedly_try(
+            one_try,
+            (),
+            (
+                &mut sinks,
+                None::<fn(&(), std::time::Instant, &mut Sinks)>,
+                Some(logger),
+            ),
+        )
+        .await;
+        assert_eq!(z, Err(()));
+        assert_eq!(sinks.network, vec![503]);
+        assert_eq!(sinks.validation, vec!["bad field"]);
+        assert_eq!(sinks.unrouted, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn ring_keeps_the_latest() {
+        use super::{RetryEventKind, RingLogger};
+        use crate::retryable::RetryableResult;
+        use crate::try_again::repeatedly_try;
+
+        let ring = RingLogger::new(3);
+        let z = repeatedly_try(
+            |()| async {
+                RetryableResult::<(), _, ()>::Retryable(HeterogeneousError::Network(503))
+            },
+            (),
+            (
+                &mut (),
+                Some(ring.fatal_logger()),
+                Some(ring.recoverable_logger()),
+            ),
+        )
+        .await;
+        assert_eq!(z, Err(()));
+        let kinds: Vec<_> = ring
+            .snapshot()
+            .into_iter()
+            .map(|e| (e.kind, e.error))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (RetryEventKind::Recoverable, "Network(503)".to_string()),
+                (RetryEventKind::Recoverable, "Network(503)".to_string()),
+                (RetryEventKind::Fatal, "()".to_string()),
+            ]
+        );
+
+        let empty = RingLogger::new(0);
+        empty.record(RetryEventKind::Fatal, &"dropped", std::time::Instant::now());
+        assert!(empty.snapshot().is_empty());
+
+        assert!(std::ptr::eq(
+            RingLogger::install_global(8),
+            RingLogger::install_global(1)
+        ));
+        assert!(RingLogger::global().is_some());
+    }
+
+    #[test]
+    fn levels_rise_with_attempts() {
+        use super::{LevelEscalation, LogLevel};
+        let default = LevelEscalation::default();
+        assert_eq!(default.level_for(1), LogLevel::Debug);
+        assert_eq!(default.level_for(3), LogLevel::Info);
+        assert_eq!(default.level_for(9), LogLevel::Info);
+        assert_eq!(default.level_for(10), LogLevel::Warn);
+        let straight_to_warn = LevelEscalation {
+            info_from: 5,
+            warn_from: 2,
+        };
+        assert_eq!(straight_to_warn.level_for(3), LogLevel::Warn);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn escalating_logger_counts_attempts() {
+        use super::{LevelEscalation, LogLevel};
+        use crate::retryable::RetryableResult;
+        use crate::try_again::repeatedly_try;
+
+        let escalation = LevelEscalation {
+            info_from: 2,
+            warn_from: 3,
+        };
+        let logger = escalation.recoverable_logger(
+            |level, e: &HeterogeneousError, _, ctx: &mut Vec<(LogLevel, String)>| {
+                ctx.push((level, format!("{e:?}")));
+            },
+        );
+        let mut logged = Vec::new();
+        let z = repeatedly_try(
+            |()| async {
+                RetryableResult::<(), _, ()>::Retryable(HeterogeneousError::Network(503))
+            },
+            (),
+            (
+                &mut logged,
+                None::<fn(&(), std::time::Instant, &mut Vec<(LogLevel, String)>)>,
+                Some(logger),
+            ),
+        )
+        .await;
+        assert_eq!(z, Err(()));
+        let levels: Vec<_> = logged.into_iter().map(|(level, _)| level).collect();
+        assert_eq!(
+            levels,
+            vec![LogLevel::Debug, LogLevel::Info, LogLevel::Warn]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! the names of the metrics every `RetryBuilder` loop reports
+//!     which includes `repeatedly_try`, `retry_if` and everything else built on it
+//! with the `metrics` feature they go to whatever recorder is installed for the `metrics` crate
+//!     so dashboards work without writing an `on_wait` hook or a logger for them
+//! without that feature nothing is recorded and these are only names
+//! this does not depend on any of the logging, the loggers still only hear about loops that gave up
+
+use std::time::Duration;
+
+/// counter, every attempt that was started, including the first one of each loop
+pub const ATTEMPTS: &str = "retry.attempts";
+/// counter, every recoverable error, whether or not it was retried
+pub const RECOVERABLE: &str = "retry.recoverable";
+/// counter, every loop that ended because an attempt gave a fatal error directly
+pub const FATAL: &str = "retry.fatal";
+/// counter, every loop that ended on a recoverable error
+///     because the policy ran out, a deadline passed, a health check or veto stopped it
+pub const EXHAUSTED: &str = "retry.exhausted";
+/// histogram, in milliseconds, every wait before a retry, with 0 for an immediate retry
+pub const WAIT_MS: &str = "retry.wait_ms";
+
+pub(crate) fn attempt() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(ATTEMPTS).increment(1);
+}
+
+pub(crate) fn recoverable() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(RECOVERABLE).increment(1);
+}
+
+pub(crate) fn fatal() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(FATAL).increment(1);
+}
+
+pub(crate) fn exhausted() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(EXHAUSTED).increment(1);
+}
+
+pub(crate) fn wait(how_long: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(WAIT_MS).record(how_long.as_secs_f64() * 1000.0);
+    #[cfg(not(feature = "metrics"))]
+    let _ = how_long;
+}
+
+mod test {
+    #[cfg(feature = "metrics")]
+    #[allow(dead_code)]
+    #[derive(Default)]
+    struct Samples(std::sync::Mutex<Vec<f64>>);
+
+    #[cfg(feature = "metrics")]
+    impl ::metrics::HistogramFn for Samples {
+        fn record(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    /// keeps every counter and the one histogram so the test can read them back
+    #[cfg(feature = "metrics")]
+    #[allow(dead_code)]
+    #[derive(Default)]
+    struct Captured {
+        counters: std::sync::Mutex<
+            std::collections::HashMap<String, std::sync::Arc<std::sync::atomic::AtomicU64>>,
+        >,
+        waits: std::sync::Arc<Samples>,
+    }
+
+    #[cfg(feature = "metrics")]
+    impl Captured {
+        #[allow(dead_code)]
+        fn count(&self, name: &str) -> u64 {
+            self.counters
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(0, |c| c.load(std::sync::atomic::Ordering::SeqCst))
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    impl ::metrics::Recorder for Captured {
+        fn describe_counter(
+            &self,
+            _: ::metrics::KeyName,
+            _: Option<::metrics::Unit>,
+            _: ::metrics::SharedString,
+        ) {
+        }
+        fn describe_gauge(
+            &self,
+            _: ::metrics::KeyName,
+            _: Option<::metrics::Unit>,
+            _: ::metrics::SharedString,
+        ) {
+        }
+        fn describe_histogram(
+            &self,
+            _: ::metrics::KeyName,
+            _: Option<::metrics::Unit>,
+            _: ::metrics::SharedString,
+        ) {
+        }
+
+        fn register_counter(
+            &self,
+            key: &::metrics::Key,
+            _: &::metrics::Metadata<'_>,
+        ) -> ::metrics::Counter {
+            let counter = std::sync::Arc::clone(
+                self.counters
+                    .lock()
+                    .unwrap()
+                    .entry(key.name().to_string())
+                    .or_default(),
+            );
+            ::metrics::Counter::from_arc(counter)
+        }
+
+        fn register_gauge(
+            &self,
+            _: &::metrics::Key,
+            _: &::metrics::Metadata<'_>,
+        ) -> ::metrics::Gauge {
+            ::metrics::Gauge::noop()
+        }
+
+        fn register_histogram(
+            &self,
+            _: &::metrics::Key,
+            _: &::metrics::Metadata<'_>,
+        ) -> ::metrics::Histogram {
+            ::metrics::Histogram::from_arc(std::sync::Arc::clone(&self.waits))
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn loop_reports_its_metrics() {
+        use super::{ATTEMPTS, EXHAUSTED, FATAL, RECOVERABLE};
+        use crate::backoff::ExponentialBackoff;
+        use crate::try_again::retry_if;
+        use std::time::Duration;
+        let captured = Captured::default();
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("a runtime for the test");
+        ::metrics::with_local_recorder(&captured, || {
+            runtime.block_on(async {
+                let z = retry_if(|()| async { Err::<(), _>("busy") }, (), |_| true, policy).await;
+                assert_eq!(z, Err("busy"));
+                let z = retry_if(|()| async { Err::<(), _>("bad") }, (), |_| false, policy).await;
+                assert_eq!(z, Err("bad"));
+            });
+        });
+        assert_eq!(captured.count(ATTEMPTS), 4);
+        assert_eq!(captured.count(RECOVERABLE), 3);
+        assert_eq!(captured.count(EXHAUSTED), 1);
+        assert_eq!(captured.count(FATAL), 1);
+        let waits: Vec<_> = captured
+            .waits
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|w| w.round())
+            .collect();
+        assert_eq!(waits, vec![1.0, 2.0]);
+    }
+}
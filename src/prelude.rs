@@ -0,0 +1,9 @@
+//! the handful of items nearly every user of the crate needs
+//!     `use retryable_result::prelude::*;`
+//! everything else, such as the other drivers and the logging helpers, is imported by name
+//!     so that this stays small enough to glob import without surprises
+
+pub use crate::backoff::{Backoff, BudgetedExponential, ExponentialBackoff};
+pub use crate::builder::RetryBuilder;
+pub use crate::retryable::{Retryable, RetryableResult, RetryableResultExt, WaitDecision};
+pub use crate::try_again::{repeatedly_try, retry_if};
@@ -0,0 +1,290 @@
+//! how a retry loop waits between attempts
+//! the deciding of how long to wait is runtime independent
+//!     so the actual waiting is the only part that has to match the runtime being used
+//! `GlobalSleeper` is the default, which is whatever `set_global_sleeper` installed at startup
+//!     or `AsyncStdSleeper` if nothing was
+//! with the `wasm` feature there is also `WasmSleeper` which is backed by `setTimeout`
+//! with the `test-no-sleep` feature in a debug build, the waits between attempts are skipped
+//!     in every driver and whatever the sleeper, so retry heavy test suites run instantly
+//!     the skipped waits are added up in `skipped_backoff` for the whole process
+//!     and each loop moves the instants it gives to its policy on by the waits it skipped
+//!     so a policy that measures time, such as `BudgetedExponential`, decides as if it had waited
+//!     attempt timeouts still wait, since skipping them would time out every attempt
+//!     and a release build ignores the feature, so it can not leak out of a test profile
+//!     turn it on under `[dev-dependencies]` so that normal builds never see it
+//! no driver waits longer than `IMPLAUSIBLE_WAIT` between attempts
+//!     a policy asking for more, such as `Duration::MAX` from an overflow, is a bug in that policy
+//!     and the wait it asked for would never end, leaving dropping the future as the only way out
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+type GlobalSleep = Box<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+static GLOBAL_SLEEPER: OnceLock<GlobalSleep> = OnceLock::new();
+
+#[cfg(all(feature = "test-no-sleep", debug_assertions))]
+static SKIPPED_BACKOFF: std::sync::Mutex<Duration> = std::sync::Mutex::new(Duration::ZERO);
+
+/// the longest any driver waits between two attempts, whatever the policy asked for
+///     `RetryBuilder::implausible_wait` sets a different limit for one loop
+pub const IMPLAUSIBLE_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub(crate) fn plausible(how_long: Duration) -> Duration {
+    how_long.min(IMPLAUSIBLE_WAIT)
+}
+
+pub(crate) fn skip_backoff(how_long: Duration) -> bool {
+    //! true when the wait between attempts should not happen
+    //!     in which case `how_long` has been added to `skipped_backoff`
+    #[cfg(all(feature = "test-no-sleep", debug_assertions))]
+    {
+        let mut skipped = SKIPPED_BACKOFF
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        *skipped = skipped.saturating_add(how_long);
+        true
+    }
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    {
+        let _ = how_long;
+        false
+    }
+}
+
+/// the clock one retry loop records its failures by
+///     the real clock plus every wait this loop skipped under `test-no-sleep`
+///     so without that feature it is just the real clock
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct LoopClock {
+    skipped: Duration,
+}
+
+impl LoopClock {
+    pub(crate) fn at(self, real: Instant) -> Instant {
+        //! `real` moved on by the waits skipped so far
+        real.checked_add(self.skipped).unwrap_or(real)
+    }
+
+    pub(crate) fn now(self) -> Instant {
+        self.at(Instant::now())
+    }
+
+    pub(crate) fn skip(&mut self, how_long: Duration) -> bool {
+        //! `skip_backoff`, with a skipped wait also moving this clock on
+        let skipped = skip_backoff(how_long);
+        if skipped {
+            self.skipped = self.skipped.saturating_add(how_long);
+        }
+        skipped
+    }
+}
+
+#[must_use]
+pub fn skipped_backoff() -> Duration {
+    //! how long every retry loop in the process would have waited by now
+    //!     if the `test-no-sleep` feature had not skipped the waits
+    //! always zero without that feature or in a release build
+    #[cfg(all(feature = "test-no-sleep", debug_assertions))]
+    {
+        *SKIPPED_BACKOFF
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    {
+        Duration::ZERO
+    }
+}
+
+pub trait Sleeper {
+    //! something that can wait for a while without blocking the thread
+    fn sleep(&self, how_long: Duration) -> impl Future<Output = ()>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncStdSleeper;
+
+impl Sleeper for AsyncStdSleeper {
+    fn sleep(&self, how_long: Duration) -> impl Future<Output = ()> {
+        async_std::task::sleep(how_long)
+    }
+}
+
+pub fn set_global_sleeper<SleepFun, Fut>(sleep: SleepFun) -> bool
+where
+    SleepFun: Fn(Duration) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    //! how every retry loop without a sleeper of its own waits, for an app that uses one runtime
+    //!     such as `|d| tokio::time::sleep(d)`
+    //! this can only be done once, so it is meant for the startup of an application
+    //!     libraries should give a sleeper to `RetryBuilder::sleeper` instead of touching this
+    //! false if there already was a global sleeper, in which case `sleep` is not used
+    GLOBAL_SLEEPER
+        .set(Box::new(move |how_long| Box::pin(sleep(how_long))))
+        .is_ok()
+}
+
+#[must_use]
+pub fn global_sleeper() -> GlobalSleeper {
+    GlobalSleeper
+}
+
+pub(crate) fn global_sleep(how_long: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    //! `GlobalSleeper` as a boxed future that does not borrow anything
+    //!     for the drivers that have no sleeper of their own to keep around
+    match GLOBAL_SLEEPER.get() {
+        Some(sleep) => sleep(how_long),
+        None => Box::pin(async_std::task::sleep(how_long)),
+    }
+}
+
+/// waits with whatever `set_global_sleeper` installed
+///     falling back to `AsyncStdSleeper` until something is installed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalSleeper;
+
+impl Sleeper for GlobalSleeper {
+    fn sleep(&self, how_long: Duration) -> impl Future<Output = ()> {
+        let installed = GLOBAL_SLEEPER.get();
+        async move {
+            match installed {
+                Some(sleep) => sleep(how_long).await,
+                None => AsyncStdSleeper.sleep(how_long).await,
+            }
+        }
+    }
+}
+
+/// waits with the browser's `setTimeout` through `gloo-timers`
+///     for retry loops in a WASM frontend where the other timers do not work
+/// `setTimeout` counts in whole milliseconds so waits are rounded up to the next millisecond
+///     and capped at `u32::MAX` milliseconds
+#[cfg(feature = "wasm")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WasmSleeper;
+
+#[cfg(feature = "wasm")]
+impl Sleeper for WasmSleeper {
+    fn sleep(&self, how_long: Duration) -> impl Future<Output = ()> {
+        let millis = how_long
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(u128::from(u32::MAX));
+        gloo_timers::future::TimeoutFuture::new(u32::try_from(millis).unwrap_or(u32::MAX))
+    }
+}
+
+mod test {
+    #[allow(dead_code)]
+    static GLOBAL_SLEEPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    #[cfg(not(all(feature = "test-no-sleep", debug_assertions)))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn global_sleeper_is_the_default() {
+        use super::set_global_sleeper;
+        use crate::builder::RetryBuilder;
+        use crate::retryable::{Retryable, RetryableResult};
+        use crate::try_again::repeatedly_try_with_async_loggers;
+        use std::sync::atomic::Ordering;
+        struct Blip;
+        impl Retryable for Blip {
+            type FatalError = ();
+            fn to_fatal(self) -> Self::FatalError {}
+            fn wait_time(
+                &self,
+                _my_time: std::time::Instant,
+                previous_retriable_failures: &[(Self, std::time::Instant)],
+            ) -> Option<std::time::Duration> {
+                (previous_retriable_failures.len() < 2)
+                    .then_some(std::time::Duration::from_millis(1))
+            }
+        }
+        let counting = |how_long| {
+            GLOBAL_SLEEPS.fetch_add(1, Ordering::SeqCst);
+            async_std::task::sleep(how_long)
+        };
+        assert!(set_global_sleeper(counting));
+        assert!(!set_global_sleeper(|_| async {}));
+        let before = GLOBAL_SLEEPS.load(Ordering::SeqCst);
+        let z = RetryBuilder::new()
+            .run(
+                |()| async { RetryableResult::<(), _, ()>::Retryable(Blip) },
+                (),
+                (
+                    &mut (),
+                    None::<fn(&(), std::time::Instant, &mut ())>,
+                    None::<fn(&Blip, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+        assert_eq!(z, Err(()));
+        assert!(GLOBAL_SLEEPS.load(Ordering::SeqCst) >= before + 2);
+
+        let before = GLOBAL_SLEEPS.load(Ordering::SeqCst);
+        let z = repeatedly_try_with_async_loggers(
+            |()| async { RetryableResult::<(), _, ()>::Retryable(Blip) },
+            (),
+            (
+                &mut (),
+                None::<fn(&(), std::time::Instant, &mut ()) -> std::future::Ready<()>>,
+                None::<fn(&Blip, std::time::Instant, &mut ()) -> std::future::Ready<()>>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err(()));
+        assert!(GLOBAL_SLEEPS.load(Ordering::SeqCst) >= before + 2);
+    }
+
+    #[cfg(all(feature = "test-no-sleep", debug_assertions))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn backoff_skipped_but_counted() {
+        use super::skipped_backoff;
+        use crate::backoff::ExponentialBackoff;
+        use crate::try_again::retry_if;
+        use std::time::{Duration, Instant};
+        let policy = ExponentialBackoff {
+            base: Duration::from_secs(10),
+            max_attempts: 3,
+            ..ExponentialBackoff::DEFAULT
+        };
+        let before = skipped_backoff();
+        let started = Instant::now();
+        let z = retry_if(|()| async { Err::<(), _>("down") }, (), |_| true, policy).await;
+        assert_eq!(z, Err("down"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(skipped_backoff() >= before + Duration::from_secs(30));
+    }
+
+    #[cfg(all(feature = "test-no-sleep", debug_assertions))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn skipped_waits_still_count_against_a_budget() {
+        use crate::backoff::BudgetedExponential;
+        use crate::try_again::retry_if;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+        let policy = BudgetedExponential {
+            base: Duration::from_millis(100),
+            multiplier: 2,
+            total_budget: Duration::from_secs(1),
+        };
+        let attempts = AtomicUsize::new(0);
+        let z = retry_if(
+            |()| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>("down")
+            },
+            (),
+            |_| true,
+            policy,
+        )
+        .await;
+        assert_eq!(z, Err("down"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+}
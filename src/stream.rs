@@ -0,0 +1,168 @@
+//! retrying each item of a stream rather than one whole operation
+//!     for a stream that can simply be polled again after an error to try the same item again
+//!     such as one that fetches the next page of results and only moves on once that worked
+//! each item gets the whole retry policy of its error type to itself
+//!     so the history of recoverable failures starts over after every item that came through
+//! the stream only ends early when the policy gives up on an item
+//!     and then its last item is that fatal error
+
+use crate::retryable::{Retryable, WaitDecision};
+use crate::sleep::{global_sleep, plausible, LoopClock};
+use futures_core::{Stream, TryStream};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Instant,
+};
+
+type Pause = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub struct RetryTryStream<S>
+where
+    S: TryStream,
+    S::Error: Retryable,
+{
+    inner: Pin<Box<S>>,
+    failures: Vec<(S::Error, Instant)>,
+    clock: LoopClock,
+    pause: Option<Pause>,
+    done: bool,
+}
+
+pub fn retry_try_stream<S>(stream: S) -> RetryTryStream<S>
+where
+    S: TryStream,
+    S::Error: Retryable,
+{
+    //! a stream of the successes of `stream`, with the recoverable errors in between retried
+    //!     by waiting as long as the error's `wait_decision` says and then polling `stream` again
+    //!     with the wait done by the `GlobalSleeper`, like a `RetryBuilder` without a sleeper of its own
+    //! once the policy gives up on an item, the `escalate`d error is the last item
+    RetryTryStream {
+        inner: Box::pin(stream),
+        failures: Vec::new(),
+        clock: LoopClock::default(),
+        pause: None,
+        done: false,
+    }
+}
+
+impl<S> Stream for RetryTryStream<S>
+where
+    S: TryStream,
+    S::Error: Retryable + Unpin,
+{
+    type Item = Result<S::Ok, <S::Error as Retryable>::FatalError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            if let Some(pause) = this.pause.as_mut() {
+                ready!(pause.as_mut().poll(cx));
+                this.pause = None;
+            }
+            match ready!(this.inner.as_mut().try_poll_next(cx)) {
+                None => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                Some(Ok(item)) => {
+                    this.failures.clear();
+                    return Poll::Ready(Some(Ok(item)));
+                }
+                Some(Err(r)) => {
+                    let this_time = this.clock.now();
+                    if r.resets_history() {
+                        this.failures.clear();
+                    }
+                    match r.wait_decision(this_time, &this.failures) {
+                        WaitDecision::GiveUp => {
+                            let f = r.escalate(&this.failures);
+                            this.failures.clear();
+                            this.done = true;
+                            return Poll::Ready(Some(Err(f)));
+                        }
+                        WaitDecision::Immediate => this.failures.push((r, this_time)),
+                        WaitDecision::Wait(how_long_to_wait) => {
+                            this.failures.push((r, this_time));
+                            let how_long_to_wait = plausible(how_long_to_wait);
+                            if !this.clock.skip(how_long_to_wait) {
+                                this.pause = Some(global_sleep(how_long_to_wait));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct PageFailed;
+
+    impl crate::retryable::Retryable for PageFailed {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "page kept failing"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 2).then_some(std::time::Duration::from_millis(1))
+        }
+    }
+
+    /// gives back each of its outcomes in turn, as if each poll fetched a page
+    #[allow(dead_code)]
+    struct Pages(std::collections::VecDeque<Result<u8, PageFailed>>);
+
+    impl futures_core::Stream for Pages {
+        type Item = Result<u8, PageFailed>;
+
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Self::Item>> {
+            std::task::Poll::Ready(self.0.pop_front())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn items_retried_until_fatal() {
+        use super::retry_try_stream;
+        use futures_core::Stream;
+        let pages = Pages(
+            [
+                Ok(1),
+                Err(PageFailed),
+                Ok(2),
+                Err(PageFailed),
+                Err(PageFailed),
+                Ok(3),
+                Err(PageFailed),
+                Err(PageFailed),
+                Err(PageFailed),
+                Ok(4),
+            ]
+            .into(),
+        );
+        let mut retried = retry_try_stream(pages);
+        let mut seen = Vec::new();
+        while let Some(item) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut retried).poll_next(cx)).await
+        {
+            seen.push(item);
+        }
+        assert_eq!(seen, vec![Ok(1), Ok(2), Ok(3), Err("page kept failing")]);
+    }
+}
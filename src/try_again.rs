@@ -4,8 +4,17 @@
 //!     - there are enough recoverable errors that the `wait_time` on `Retryable` says it is time to give up
 //!     - a fatal error
 
-use crate::retryable::{Retryable, RetryableResult};
-use std::{future::Future, time::Instant};
+use crate::backoff::Backoff;
+use crate::builder::RetryBuilder;
+use crate::give_up::GaveUp;
+use crate::retryable::{Retryable, RetryableResult, WaitDecision};
+use crate::sleep::{global_sleep, plausible, LoopClock};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    future::Future,
+    time::{Duration, Instant},
+};
 
 #[allow(dead_code)]
 trait ArgType
@@ -14,7 +23,6 @@ where
 {
 }
 
-#[allow(clippy::needless_for_each)]
 #[allow(dead_code)]
 pub async fn repeatedly_try<
     SuccessType,
@@ -48,48 +56,598 @@ where
     //!     that is we should just give up
     //! otherwise we are just repeatedly getting recoverable errors and we wait for some time determined by when
     //!     which recoverable errors we saw and when
+    //!     or try again right away if `wait_decision` said `Immediate`
     //! when the entire thing results in a fatal error the chain of recoverable errors and final fatal error
     //!     go into the logging functions
+    //! the returned future is `Send`, so it can go to `tokio::spawn`, when all of these are
+    //!     `do_this_function`, `arg`, the futures it makes, `RecoverableErr`, `FatalErr`, `SuccessType`
+    //!     the loggers and `FailLogContext`
+    //!     and `'static` as well when spawning, so make the log context inside the spawned task
+    //! the variants that hold a `RefCell` across an attempt, such as `repeatedly_try_with_setup`
+    //!     are never `Send` and have to be awaited on the task that made them
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    RetryBuilder::new()
+        .run(do_this_function, arg, loggers)
+        .await
+}
+
+pub async fn repeatedly_try_simple<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    OneTryFun: Fn() -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! `repeatedly_try` for a `do_this_function` that takes no argument
+    //!     because it captures everything it needs
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    repeatedly_try(|()| do_this_function(), (), loggers).await
+}
+
+pub async fn repeatedly_try_cow<
+    'a,
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: Cow<'a, ArgType>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: ToOwned + ?Sized,
+    OneTryFun: Fn(Cow<'a, ArgType>) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! `repeatedly_try` for an argument that is borrowed by some callers and owned by others
+    //!     such as a `str` or a slice, without needing `ArgType: Clone`
+    //! a `Cow::Borrowed` argument is handed to every attempt as the same borrow, so nothing is cloned
+    //!     and only an attempt that calls `into_owned` pays for a copy
+    //! a `Cow::Owned` argument is cloned for every attempt, just as `repeatedly_try` would
+    //!     so an owned value that is expensive to clone is better borrowed by the caller
+    //! unlike a borrowed `&ArgType` given to `repeatedly_try`, the closure sees one type
+    //!     whichever the caller had, at the price of matching on the `Cow`
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    repeatedly_try(do_this_function, arg, loggers).await
+}
+
+/// an error that `retry_if` was told to retry, along with the policy that says how
+struct RetryIfRecoverable<'b, E, B> {
+    error: E,
+    backoff: &'b B,
+}
+
+impl<E, B> Retryable for RetryIfRecoverable<'_, E, B>
+where
+    B: Backoff,
+{
+    type FatalError = E;
+
+    fn to_fatal(self) -> Self::FatalError {
+        self.error
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        self.backoff
+            .next_delay(my_time, previous_retriable_failures)
+    }
+}
+
+pub async fn retry_if<SuccessType, ErrType, ArgType, OneTryFun, Fut0, ShouldRetry, BackoffType>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    should_retry: ShouldRetry,
+    backoff: BackoffType,
+) -> Result<SuccessType, ErrType>
+where
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, ErrType>>,
+    ShouldRetry: Fn(&ErrType) -> bool,
+    BackoffType: Backoff,
+{
+    //! the place to start, with no traits to implement and no `RetryableResult` to build
+    //! `do_this_function` gives an ordinary `Result`
+    //!     and `should_retry` says which of its errors are worth another attempt
+    //! `backoff` decides how long to wait and when to give up, such as an `ExponentialBackoff`
+    //! when it gives up the error is the last one `do_this_function` gave
+    //! this is `repeatedly_try` with no loggers
+    //!     so use that instead once an error type with its own `Retryable` is worth writing
+    //! # Errors
+    //! the last error, when it was one `should_retry` said no to or `backoff` said to give up
+    let backoff = &backoff;
+    let should_retry = &should_retry;
+    let one_try = |arg| {
+        let attempt = do_this_function(arg);
+        async move {
+            match attempt.await {
+                Ok(z) => RetryableResult::GoodResult(z),
+                Err(error) if should_retry(&error) => {
+                    RetryableResult::Retryable(RetryIfRecoverable { error, backoff })
+                }
+                Err(error) => RetryableResult::Fatal(error),
+            }
+        }
+    };
+    repeatedly_try(
+        one_try,
+        arg,
+        (
+            &mut (),
+            None::<fn(&ErrType, Instant, &mut ())>,
+            None::<fn(&RetryIfRecoverable<'_, ErrType, BackoffType>, Instant, &mut ())>,
+        ),
+    )
+    .await
+}
+
+/// a classifier chosen at runtime, such as from the routing config of a gateway
+///     so that different request types can share one driver
+pub type DynClassifier<'a, ErrType, SuccessType, RecoverableErr, FatalErr> = Box<
+    dyn Fn(ErrType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr> + Send + Sync + 'a,
+>;
+
+pub async fn repeatedly_try_classified<
+    SuccessType,
+    ErrType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    classify: &dyn Fn(ErrType) -> RetryableResult<SuccessType, RecoverableErr, FatalErr>,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = Result<SuccessType, ErrType>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try` for a `do_this_function` that gives an ordinary `Result`
+    //!     with each error turned into a `RetryableResult` by `classify`
+    //! `classify` is dynamically dispatched so it can be picked at runtime
+    //!     such as a `DynClassifier` out of a map keyed by request type
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or `classify` gave a `FatalErr`
+    let one_try = |arg| {
+        let attempt = do_this_function(arg);
+        async move {
+            match attempt.await {
+                Ok(z) => RetryableResult::GoodResult(z),
+                Err(e) => classify(e),
+            }
+        }
+    };
+    repeatedly_try(one_try, arg, loggers).await
+}
+
+pub async fn repeatedly_try_with_history<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<(SuccessType, Vec<(RecoverableErr, Instant)>), FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try` but a success comes with every recoverable error before it
+    //!     and when each happened, oldest first
+    //!     for a report of what a call got through on its way to succeeding
+    //! the loggers are still only called when it gives up
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    RetryBuilder::new()
+        .run_with_history(do_this_function, arg, loggers)
+        .await
+        .map_err(GaveUp::into_error)
+}
+
+pub async fn repeatedly_try_with_setup<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    AttemptCtx,
+    OneTryFun,
+    SetupFun,
+    TeardownFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    setup: SetupFun,
+    teardown: TeardownFun,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType, AttemptCtx) -> Fut0,
+    SetupFun: FnMut() -> AttemptCtx,
+    TeardownFun: FnMut(AttemptCtx),
+    Fut0: Future<
+        Output = (
+            RetryableResult<SuccessType, RecoverableErr, FatalErr>,
+            AttemptCtx,
+        ),
+    >,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the same as `repeatedly_try` with a fresh context for every attempt
+    //!     such as a scratch buffer or a connection that should not be reused after a failure
+    //! `setup` makes the context before each attempt and `do_this_function` is given it
+    //!     and hands it back along with how the attempt went
+    //! `teardown` is given it back right after each attempt, the successful one included
+    //!     so after a recoverable error it is torn down before the wait
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let setup = RefCell::new(setup);
+    let teardown = RefCell::new(teardown);
+    let one_try = |arg| {
+        let attempt_ctx = (setup.borrow_mut())();
+        let attempt = do_this_function(arg, attempt_ctx);
+        let teardown = &teardown;
+        async move {
+            let (outcome, attempt_ctx) = attempt.await;
+            (teardown.borrow_mut())(attempt_ctx);
+            outcome
+        }
+    };
+    repeatedly_try(one_try, arg, loggers).await
+}
+
+tokio::task_local! {
+    static CURRENT_ATTEMPT: usize;
+}
+
+#[must_use]
+pub fn current_attempt() -> Option<usize> {
+    //! which attempt of the innermost retry loop this is running in, 1 for the first one
+    //!     so that code far from the retry site can tag its metrics or logs
+    //!     without the number being threaded through every function
+    //! None when not inside an attempt of one of the async retry loops
+    CURRENT_ATTEMPT.try_with(|attempt| *attempt).ok()
+}
+
+pub(crate) async fn as_attempt<Fut, MakeFut>(attempt: usize, make_attempt: MakeFut) -> Fut::Output
+where
+    MakeFut: FnOnce() -> Fut,
+    Fut: Future,
+{
+    //! run one attempt with `current_attempt` giving `attempt`
+    //!     both while calling the function and while awaiting what it gave
+    CURRENT_ATTEMPT
+        .scope(attempt, async move { make_attempt().await })
+        .await
+}
+
+#[allow(clippy::needless_for_each)]
+pub(crate) fn log_and_give_up<
+    RecoverableErr,
+    FatalErr,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+>(
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+    my_retriable_failures: &[(RecoverableErr, Instant)],
+    f: FatalErr,
+    this_time: Instant,
+) -> FatalErr
+where
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+{
+    //! the whole thing resulted in a fatal error
+    //! so the chain of recoverable errors and the final fatal error go into the logging functions
+    let (ctx, fatal_logger, recoverable_logger) = loggers;
+    if let Some(recoverable_logger) = recoverable_logger {
+        my_retriable_failures.iter().for_each(|(a, b)| {
+            recoverable_logger(a, *b, ctx);
+        });
+    }
+    if let Some(fatal_logger) = fatal_logger {
+        fatal_logger(&f, this_time, ctx);
+    }
+    f
+}
+
+pub(crate) async fn log_and_give_up_async<
+    RecoverableErr,
+    FatalErr,
+    FailLogContext,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    FatalLogFut,
+    RecoverableLogFut,
+>(
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+    my_retriable_failures: &[(RecoverableErr, Instant)],
+    f: FatalErr,
+    this_time: Instant,
+) -> FatalErr
+where
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext) -> FatalLogFut,
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext) -> RecoverableLogFut,
+    FatalLogFut: Future<Output = ()>,
+    RecoverableLogFut: Future<Output = ()>,
+{
+    //! `log_and_give_up` awaiting each logger in turn
+    //!     so the recoverable errors are logged in order and the fatal error is logged last
+    let (ctx, fatal_logger, recoverable_logger) = loggers;
+    if let Some(recoverable_logger) = recoverable_logger {
+        for (a, b) in my_retriable_failures {
+            recoverable_logger(a, *b, ctx).await;
+        }
+    }
+    if let Some(fatal_logger) = fatal_logger {
+        fatal_logger(&f, this_time, ctx).await;
+    }
+    f
+}
+
+pub async fn repeatedly_try_with_async_loggers<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    FatalLogFut,
+    RecoverableLogFut,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext) -> FatalLogFut,
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext) -> RecoverableLogFut,
+    FatalLogFut: Future<Output = ()>,
+    RecoverableLogFut: Future<Output = ()>,
+{
+    //! the same as `repeatedly_try` except that the loggers return futures which are awaited
+    //!     so that logging the fatal error can flush to a remote sink before this returns
+    //! the loggers are only called once it has given up, just like with `repeatedly_try`
+    //!     so they never hold up the waits between attempts
+    //! the returned futures can not borrow the error or the context
+    //!     so take whatever is needed from them before the `async` block
     //! # Errors
     //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
-    let mut my_retriable_failures = Vec::<(RecoverableErr, Instant)>::with_capacity(5);
+    let mut my_retriable_failures = Vec::<(RecoverableErr, Instant)>::new();
+    let mut my_attempt_durations = Vec::<Duration>::new();
+    let mut clock = LoopClock::default();
+    let mut attempt = 0;
     loop {
-        let cur_trial = do_this_function(arg.clone()).await;
-        match cur_trial {
+        attempt += 1;
+        let attempt_start = Instant::now();
+        match as_attempt(attempt, || do_this_function(arg.clone())).await {
             RetryableResult::GoodResult(z) => {
                 return Ok(z);
             }
             RetryableResult::Retryable(r) => {
-                let this_time = Instant::now();
-                if let Some(how_long_to_wait) = r.wait_time(this_time, &my_retriable_failures) {
-                    my_retriable_failures.push((r, this_time));
-                    async_std::task::sleep(how_long_to_wait).await;
-                } else {
-                    let (ctx, fatal_logger, recoverable_logger) = loggers;
-                    if let Some(recoverable_logger) = recoverable_logger {
-                        my_retriable_failures.iter().for_each(|(a, b)| {
-                            recoverable_logger(a, *b, ctx);
-                        });
-                    }
-                    let f = r.to_fatal();
-                    if let Some(fatal_logger) = fatal_logger {
-                        fatal_logger(&f, this_time, ctx);
+                let this_attempt_took = attempt_start.elapsed();
+                let this_time = clock.now();
+                if r.resets_history() {
+                    my_retriable_failures.clear();
+                    my_attempt_durations.clear();
+                }
+                let decision = r.timed_wait_decision(
+                    this_time,
+                    this_attempt_took,
+                    &my_retriable_failures,
+                    &my_attempt_durations,
+                );
+                if decision == WaitDecision::GiveUp {
+                    let f = r.escalate(&my_retriable_failures);
+                    return Err(log_and_give_up_async(
+                        loggers,
+                        &my_retriable_failures,
+                        f,
+                        this_time,
+                    )
+                    .await);
+                }
+                my_retriable_failures.push((r, this_time));
+                my_attempt_durations.push(this_attempt_took);
+                if let WaitDecision::Wait(how_long_to_wait) = decision {
+                    let how_long_to_wait = plausible(how_long_to_wait);
+                    if !clock.skip(how_long_to_wait) {
+                        global_sleep(how_long_to_wait).await;
                     }
-                    return Err(f);
                 }
             }
             RetryableResult::Fatal(f) => {
-                let this_time = Instant::now();
-                let (ctx, fatal_logger, recoverable_logger) = loggers;
-                if let Some(recoverable_logger) = recoverable_logger {
-                    my_retriable_failures.iter().for_each(|(a, b)| {
-                        recoverable_logger(a, *b, ctx);
-                    });
+                let this_time = clock.now();
+                return Err(
+                    log_and_give_up_async(loggers, &my_retriable_failures, f, this_time).await,
+                );
+            }
+        }
+    }
+}
+
+pub trait AsyncBackoff {
+    //! like `Backoff` but deciding the next delay may itself do I/O
+    //!     such as asking a shared rate limiter service
+    //! returning None means give up
+    fn next_delay<R>(
+        &mut self,
+        my_time: Instant,
+        previous_retriable_failures: &[(R, Instant)],
+    ) -> impl Future<Output = Option<Duration>>;
+}
+
+pub async fn repeatedly_try_with_async_backoff<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    AsyncBackoffType,
+>(
+    do_this_function: OneTryFun,
+    arg: ArgType,
+    backoff: &mut AsyncBackoffType,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    AsyncBackoffType: AsyncBackoff,
+{
+    //! the same as `repeatedly_try` except that how long to wait is decided by awaiting `backoff`
+    //!     instead of by `wait_time` on the recoverable error
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let mut my_retriable_failures = Vec::<(RecoverableErr, Instant)>::new();
+    let mut clock = LoopClock::default();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let cur_trial = as_attempt(attempt, || do_this_function(arg.clone())).await;
+        match cur_trial {
+            RetryableResult::GoodResult(z) => {
+                return Ok(z);
+            }
+            RetryableResult::Retryable(r) => {
+                let this_time = clock.now();
+                if r.resets_history() {
+                    my_retriable_failures.clear();
                 }
-                if let Some(fatal_logger) = fatal_logger {
-                    fatal_logger(&f, this_time, ctx);
+                if let Some(how_long_to_wait) =
+                    backoff.next_delay(this_time, &my_retriable_failures).await
+                {
+                    my_retriable_failures.push((r, this_time));
+                    let how_long_to_wait = plausible(how_long_to_wait);
+                    if !clock.skip(how_long_to_wait) {
+                        global_sleep(how_long_to_wait).await;
+                    }
+                } else {
+                    let f = r.escalate(&my_retriable_failures);
+                    return Err(log_and_give_up(
+                        loggers,
+                        &my_retriable_failures,
+                        f,
+                        this_time,
+                    ));
                 }
-                return Err(f);
+            }
+            RetryableResult::Fatal(f) => {
+                let this_time = clock.now();
+                return Err(log_and_give_up(
+                    loggers,
+                    &my_retriable_failures,
+                    f,
+                    this_time,
+                ));
             }
         }
     }
@@ -126,9 +684,12 @@ mod test {
             //! exponential backoff
             //! if this was the first time a recoverable error happened, it waits 1 second for the 2nd try
             //! if this wait time gets to be greater than a minute then give up completely
+            //! a gap shorter than the first wait, such as two failures at the same instant,
+            //!     is treated as the first wait so that doubling it can not get stuck at zero
             let default_duration = std::time::Duration::from_millis(1000);
             if let Some((_, last_time)) = previous_retriable_failures.last() {
                 if let Some(last_two_gap) = my_time.checked_duration_since(*last_time) {
+                    let last_two_gap = last_two_gap.max(default_duration);
                     if last_two_gap > std::time::Duration::from_millis(30000) {
                         dbg!("Give up");
                         None
@@ -160,7 +721,7 @@ mod test {
         use crate::retryable::RetryableResult;
         async fn one_try(u: u8) -> RetryableResult<u8, RetryingStatusCode, StatusCode> {
             #[allow(clippy::collapsible_else_if)]
-            if u % 2 == 0 {
+            if u.is_multiple_of(2) {
                 RetryableResult::GoodResult(u >> 1)
             } else {
                 if rand::random() {
@@ -191,4 +752,536 @@ mod test {
             assert_eq!(z, Err(StatusCode::from_u16(200).expect("200 is valid")));
         }
     }
+
+    #[allow(dead_code)]
+    struct SharedLimiter {
+        consultations: usize,
+    }
+
+    impl super::AsyncBackoff for SharedLimiter {
+        async fn next_delay<R>(
+            &mut self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(R, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            async_std::task::yield_now().await;
+            self.consultations += 1;
+            if previous_retriable_failures.len() < 2 {
+                Some(std::time::Duration::from_millis(1))
+            } else {
+                None
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn async_backoff_decides() {
+        use super::repeatedly_try_with_async_backoff;
+        use crate::retryable::RetryableResult;
+        async fn one_try(_: ()) -> RetryableResult<u8, RetryingStatusCode, StatusCode> {
+            RetryableResult::Retryable(RetryingStatusCode::from_u16(503).expect("503 is valid"))
+        }
+        let mut limiter = SharedLimiter { consultations: 0 };
+        let z = repeatedly_try_with_async_backoff(
+            one_try,
+            (),
+            &mut limiter,
+            (&mut (), Some(dummy_logger2), Some(dummy_logger1)),
+        )
+        .await;
+        assert_eq!(z, Err(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(limiter.consultations, 3);
+    }
+
+    #[allow(dead_code)]
+    struct LockConflict;
+
+    impl Retryable for LockConflict {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "too much contention"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            Some(std::time::Duration::from_secs(3600))
+        }
+
+        fn wait_decision(
+            &self,
+            my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> crate::retryable::WaitDecision {
+            use crate::backoff::{Backoff, ExponentialBackoff};
+            let cap = ExponentialBackoff {
+                base: std::time::Duration::from_secs(3600),
+                multiplier: 1,
+                max_wait: std::time::Duration::from_secs(3600),
+                max_attempts: 4,
+            };
+            crate::retryable::WaitDecision::immediate_unless_give_up(
+                cap.next_delay(my_time, previous_retriable_failures),
+            )
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn immediate_retries_respect_cap() {
+        use super::repeatedly_try;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { RetryableResult::<(), _, _>::Retryable(LockConflict) }
+        };
+        let started = std::time::Instant::now();
+        let z = repeatedly_try(
+            one_try,
+            (),
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("too much contention"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[allow(dead_code)]
+    struct MaybeOverloaded;
+
+    impl Retryable for MaybeOverloaded {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            "overloaded"
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            _previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            Some(std::time::Duration::from_millis(1))
+        }
+
+        fn timed_wait_decision(
+            &self,
+            my_time: std::time::Instant,
+            my_attempt_duration: std::time::Duration,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+            previous_attempt_durations: &[std::time::Duration],
+        ) -> crate::retryable::WaitDecision {
+            //! fast failures retry, but a second slow failure gives up
+            assert_eq!(
+                previous_retriable_failures.len(),
+                previous_attempt_durations.len()
+            );
+            let slow = std::time::Duration::from_millis(20);
+            let already_slow = previous_attempt_durations.iter().any(|d| *d >= slow);
+            if my_attempt_duration >= slow && already_slow {
+                crate::retryable::WaitDecision::GiveUp
+            } else {
+                self.wait_decision(my_time, previous_retriable_failures)
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn attempt_durations_reach_policy() {
+        use super::repeatedly_try;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let attempts = AtomicUsize::new(0);
+        let one_try = |()| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which % 2 == 1 {
+                    async_std::task::sleep(std::time::Duration::from_millis(25)).await;
+                }
+                RetryableResult::<(), _, _>::Retryable(MaybeOverloaded)
+            }
+        };
+        let z = repeatedly_try(
+            one_try,
+            (),
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&MaybeOverloaded, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("overloaded"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[allow(dead_code)]
+    #[derive(Default)]
+    struct RemoteSink {
+        shipped: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn async_loggers_flush_before_returning() {
+        use super::repeatedly_try_with_async_loggers;
+        use crate::retryable::RetryableResult;
+        let mut sink = RemoteSink::default();
+        let fatal_logger = |e: &&'static str, _: std::time::Instant, ctx: &mut RemoteSink| {
+            let shipped = ctx.shipped.clone();
+            let line = format!("fatal {e}");
+            async move {
+                async_std::task::yield_now().await;
+                shipped.lock().unwrap().push(line);
+            }
+        };
+        let recoverable_logger =
+            |_: &MaybeOverloaded, _: std::time::Instant, ctx: &mut RemoteSink| {
+                let shipped = ctx.shipped.clone();
+                async move {
+                    async_std::task::yield_now().await;
+                    shipped.lock().unwrap().push("recoverable".to_string());
+                }
+            };
+        let z = repeatedly_try_with_async_loggers(
+            |which: u8| async move {
+                if which == 0 {
+                    RetryableResult::<(), MaybeOverloaded, _>::Fatal("broken")
+                } else {
+                    RetryableResult::GoodResult(())
+                }
+            },
+            0,
+            (&mut sink, Some(fatal_logger), Some(recoverable_logger)),
+        )
+        .await;
+        assert_eq!(z, Err("broken"));
+        assert_eq!(*sink.shipped.lock().unwrap(), vec!["fatal broken"]);
+
+        let z = repeatedly_try_with_async_loggers(
+            |()| async { RetryableResult::<(), _, _>::Retryable(LockConflict) },
+            (),
+            (
+                &mut sink,
+                Some(fatal_logger),
+                None::<
+                    fn(
+                        &LockConflict,
+                        std::time::Instant,
+                        &mut RemoteSink,
+                    ) -> std::future::Ready<()>,
+                >,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("too much contention"));
+        assert_eq!(
+            *sink.shipped.lock().unwrap(),
+            vec!["fatal broken", "fatal too much contention"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn retry_if_classifies_at_call_site() {
+        use super::retry_if;
+        use crate::backoff::ExponentialBackoff;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let policy = ExponentialBackoff {
+            base: std::time::Duration::from_millis(1),
+            multiplier: 2,
+            max_wait: std::time::Duration::from_millis(10),
+            max_attempts: 3,
+        };
+        let attempts = AtomicUsize::new(0);
+        let flaky = |x: u8| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if which < 2 {
+                    Err(std::io::ErrorKind::TimedOut)
+                } else {
+                    Ok(x)
+                }
+            }
+        };
+        let retry_timeouts = |e: &std::io::ErrorKind| *e == std::io::ErrorKind::TimedOut;
+        assert_eq!(retry_if(flaky, 7, retry_timeouts, policy).await, Ok(7));
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+        let always_timing_out = |()| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { Err::<(), _>(std::io::ErrorKind::TimedOut) }
+        };
+        assert_eq!(
+            retry_if(always_timing_out, (), retry_timeouts, policy).await,
+            Err(std::io::ErrorKind::TimedOut)
+        );
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+        let denied = |()| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { Err::<(), _>(std::io::ErrorKind::PermissionDenied) }
+        };
+        assert_eq!(
+            retry_if(denied, (), retry_timeouts, policy).await,
+            Err(std::io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn runtime_selected_classifier() {
+        use super::{repeatedly_try_classified, DynClassifier};
+        use crate::retryable::RetryableResult;
+        use std::collections::HashMap;
+        let mut by_route: HashMap<&str, DynClassifier<'_, u16, (), LockConflict, &'static str>> =
+            HashMap::new();
+        by_route.insert(
+            "strict",
+            Box::new(|_| RetryableResult::Fatal("no retries on this route")),
+        );
+        by_route.insert(
+            "lenient",
+            Box::new(|code| {
+                if code == 409 {
+                    RetryableResult::Retryable(LockConflict)
+                } else {
+                    RetryableResult::Fatal("not a conflict")
+                }
+            }),
+        );
+        let conflict = |()| async { Err(409) };
+        for (route, expected) in [
+            ("strict", "no retries on this route"),
+            ("lenient", "too much contention"),
+        ] {
+            let z = repeatedly_try_classified(
+                conflict,
+                (),
+                &*by_route[route],
+                (
+                    &mut (),
+                    None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                    None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+                ),
+            )
+            .await;
+            assert_eq!(z, Err(expected));
+        }
+        let z = repeatedly_try_classified(
+            |()| async { Ok::<_, u16>(()) },
+            (),
+            &*by_route["strict"],
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn attempt_number_from_deep_inside() {
+        use super::{current_attempt, repeatedly_try};
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        fn deeply_nested_metrics_tag() -> String {
+            format!("attempt={:?}", current_attempt())
+        }
+        assert_eq!(current_attempt(), None);
+        let tags = Mutex::new(Vec::new());
+        let one_try = |()| {
+            tags.lock().unwrap().push(deeply_nested_metrics_tag());
+            async {
+                async_std::task::yield_now().await;
+                let tag = deeply_nested_metrics_tag();
+                tags.lock().unwrap().push(tag);
+                RetryableResult::<(), _, _>::Retryable(LockConflict)
+            }
+        };
+        let z = repeatedly_try(
+            one_try,
+            (),
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Err("too much contention"));
+        let expected: Vec<_> = (1..=4)
+            .flat_map(|n| [format!("attempt=Some({n})"), format!("attempt=Some({n})")])
+            .collect();
+        assert_eq!(*tags.lock().unwrap(), expected);
+        assert_eq!(current_attempt(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn fresh_context_each_attempt() {
+        use super::repeatedly_try_with_setup;
+        use crate::retryable::RetryableResult;
+        use std::cell::RefCell;
+        let events = RefCell::new(Vec::new());
+        let mut connections = 0;
+        let z = repeatedly_try_with_setup(
+            |(), connection: u32| {
+                events.borrow_mut().push(format!("use {connection}"));
+                async move {
+                    let outcome = if connection < 3 {
+                        RetryableResult::Retryable(LockConflict)
+                    } else {
+                        RetryableResult::GoodResult(connection)
+                    };
+                    (outcome, connection)
+                }
+            },
+            (),
+            || {
+                connections += 1;
+                events.borrow_mut().push(format!("open {connections}"));
+                connections
+            },
+            |connection| events.borrow_mut().push(format!("close {connection}")),
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok(3));
+        assert_eq!(
+            events.into_inner(),
+            [
+                "open 1", "use 1", "close 1", "open 2", "use 2", "close 2", "open 3", "use 3",
+                "close 3"
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn success_keeps_its_history() {
+        use super::{repeatedly_try_with_history, RetryIfRecoverable};
+        use crate::backoff::ExponentialBackoff;
+        use crate::retryable::RetryableResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::{Duration, Instant};
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(1),
+            ..ExponentialBackoff::DEFAULT
+        };
+        let attempts = AtomicUsize::new(0);
+        let hiccups = |x: u8| {
+            let which = attempts.fetch_add(1, Ordering::SeqCst);
+            let policy = &policy;
+            async move {
+                match which {
+                    0 => RetryableResult::Retryable(RetryIfRecoverable {
+                        error: "dns",
+                        backoff: policy,
+                    }),
+                    1 => RetryableResult::Retryable(RetryIfRecoverable {
+                        error: "reset",
+                        backoff: policy,
+                    }),
+                    _ => RetryableResult::GoodResult(x),
+                }
+            }
+        };
+        let no_fatal_logger = None::<fn(&&'static str, Instant, &mut ())>;
+        let no_recoverable_logger =
+            None::<fn(&RetryIfRecoverable<&'static str, ExponentialBackoff>, Instant, &mut ())>;
+        let z = repeatedly_try_with_history(
+            hiccups,
+            5,
+            (&mut (), no_fatal_logger, no_recoverable_logger),
+        )
+        .await;
+        let (value, history) = z.map_err(|_| ()).expect("third attempt succeeds");
+        assert_eq!(value, 5);
+        let errors: Vec<_> = history.iter().map(|(r, _)| r.error).collect();
+        assert_eq!(errors, vec!["dns", "reset"]);
+        assert!(history[0].1 <= history[1].1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn nullary_closure_retried() {
+        use super::repeatedly_try_simple;
+        use crate::retryable::RetryableResult;
+        use std::sync::Mutex;
+        let not_clone = Mutex::new(vec!["conflict", "conflict"]);
+        let z = repeatedly_try_simple(
+            || {
+                let next = not_clone.lock().unwrap().pop();
+                async move {
+                    match next {
+                        Some(_) => RetryableResult::Retryable(LockConflict),
+                        None => RetryableResult::GoodResult("committed"),
+                    }
+                }
+            },
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok("committed"));
+        assert!(not_clone.into_inner().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn borrowed_cow_never_cloned() {
+        use super::repeatedly_try_cow;
+        use crate::retryable::RetryableResult;
+        use std::borrow::Cow;
+        use std::sync::Mutex;
+        let borrowed_each_time = Mutex::new(Vec::new());
+        let one_try = |query: Cow<'_, str>| {
+            let mut seen = borrowed_each_time.lock().unwrap();
+            seen.push(matches!(query, Cow::Borrowed(_)));
+            let answer = (seen.len() >= 3).then(|| query.into_owned());
+            async move {
+                match answer {
+                    Some(answer) => RetryableResult::GoodResult(answer),
+                    None => RetryableResult::Retryable(LockConflict),
+                }
+            }
+        };
+        let no_loggers = (
+            None::<fn(&&'static str, std::time::Instant, &mut ())>,
+            None::<fn(&LockConflict, std::time::Instant, &mut ())>,
+        );
+        let query = String::from("select 1");
+        let z = repeatedly_try_cow(
+            one_try,
+            Cow::Borrowed(query.as_str()),
+            (&mut (), no_loggers.0, no_loggers.1),
+        )
+        .await;
+        assert_eq!(z.as_deref(), Ok("select 1"));
+
+        let z = repeatedly_try_cow(
+            one_try,
+            Cow::Owned(String::from("select 2")),
+            (&mut (), no_loggers.0, no_loggers.1),
+        )
+        .await;
+        assert_eq!(z.as_deref(), Ok("select 2"));
+        assert_eq!(
+            *borrowed_each_time.lock().unwrap(),
+            vec![true, true, true, false]
+        );
+    }
 }
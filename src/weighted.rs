@@ -0,0 +1,146 @@
+//! spreading retries over a weighted pool of targets
+//! each attempt picks its argument afresh from the pool
+//!     so that one flaky node does not get all of the retries
+
+use crate::retryable::{Retryable, RetryableResult};
+use crate::try_again::repeatedly_try;
+use rand::distributions::{Distribution, WeightedError, WeightedIndex};
+use rand::Rng;
+use std::{future::Future, sync::Mutex, time::Instant};
+
+#[derive(Debug, Clone)]
+pub struct WeightedTargets<ArgType> {
+    targets: Vec<ArgType>,
+    weights: WeightedIndex<u32>,
+}
+
+impl<ArgType> WeightedTargets<ArgType>
+where
+    ArgType: Clone,
+{
+    pub fn new(targets_and_weights: Vec<(ArgType, u32)>) -> Result<Self, WeightedError> {
+        //! a target with weight 0 is never picked
+        //! # Errors
+        //! if there are no targets or all of the weights are 0
+        let (targets, weights): (Vec<_>, Vec<_>) = targets_and_weights.into_iter().unzip();
+        Ok(Self {
+            targets,
+            weights: WeightedIndex::new(weights)?,
+        })
+    }
+
+    pub fn pick<Rng0: Rng + ?Sized>(&self, rng: &mut Rng0) -> ArgType {
+        //! one target, with probability proportional to its weight
+        self.targets[self.weights.sample(rng)].clone()
+    }
+}
+
+pub async fn repeatedly_try_weighted<
+    SuccessType,
+    RecoverableErr,
+    FatalErr,
+    ArgType,
+    OneTryFun,
+    FailLogContext,
+    Fut0,
+    FatalLoggerType,
+    RecoverableLoggerType,
+    Rng0,
+>(
+    do_this_function: OneTryFun,
+    targets: &WeightedTargets<ArgType>,
+    rng: Rng0,
+    loggers: (
+        &mut FailLogContext,
+        Option<FatalLoggerType>,
+        Option<RecoverableLoggerType>,
+    ),
+) -> Result<SuccessType, FatalErr>
+where
+    RecoverableErr: Retryable<FatalError = FatalErr>,
+    ArgType: Sized + Clone,
+    OneTryFun: Fn(ArgType) -> Fut0,
+    Fut0: Future<Output = RetryableResult<SuccessType, RecoverableErr, FatalErr>>,
+    FatalLoggerType: Fn(&FatalErr, Instant, &mut FailLogContext),
+    RecoverableLoggerType: Fn(&RecoverableErr, Instant, &mut FailLogContext),
+    Rng0: Rng,
+{
+    //! the same as `repeatedly_try` except that every attempt, the first one included,
+    //!     calls `do_this_function` with a target picked from `targets` using `rng`
+    //! # Errors
+    //! when there are too many recoverable errors to a level of a breaking point or one of the steps gave a `FatalErr` directly
+    let rng = Mutex::new(rng);
+    let pick_and_try = |()| {
+        let target = {
+            let mut rng = rng
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            targets.pick(&mut *rng)
+        };
+        do_this_function(target)
+    };
+    repeatedly_try(pick_and_try, (), loggers).await
+}
+
+mod test {
+    use crate::retryable::Retryable;
+
+    #[allow(dead_code)]
+    struct NodeDown(&'static str);
+
+    impl Retryable for NodeDown {
+        type FatalError = &'static str;
+
+        fn to_fatal(self) -> Self::FatalError {
+            self.0
+        }
+
+        fn wait_time(
+            &self,
+            _my_time: std::time::Instant,
+            previous_retriable_failures: &[(Self, std::time::Instant)],
+        ) -> Option<std::time::Duration> {
+            (previous_retriable_failures.len() < 50).then_some(std::time::Duration::ZERO)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn picks_a_target_each_attempt() {
+        use super::{repeatedly_try_weighted, WeightedTargets};
+        use crate::retryable::RetryableResult;
+        use rand::SeedableRng;
+        use std::sync::Mutex;
+
+        assert!(WeightedTargets::<&str>::new(vec![]).is_err());
+        assert!(WeightedTargets::new(vec![("only", 0)]).is_err());
+
+        let targets =
+            WeightedTargets::new(vec![("flaky", 3), ("healthy", 1), ("drained", 0)]).unwrap();
+        let picked = Mutex::new(Vec::new());
+        let one_try = |target: &'static str| {
+            picked.lock().unwrap().push(target);
+            async move {
+                if target == "healthy" {
+                    RetryableResult::GoodResult(target)
+                } else {
+                    RetryableResult::Retryable(NodeDown(target))
+                }
+            }
+        };
+        let z = repeatedly_try_weighted(
+            one_try,
+            &targets,
+            rand::rngs::StdRng::seed_from_u64(7),
+            (
+                &mut (),
+                None::<fn(&&'static str, std::time::Instant, &mut ())>,
+                None::<fn(&NodeDown, std::time::Instant, &mut ())>,
+            ),
+        )
+        .await;
+        assert_eq!(z, Ok("healthy"));
+        let picked = picked.into_inner().unwrap();
+        assert_eq!(picked.last(), Some(&"healthy"));
+        assert!(picked[..picked.len() - 1].iter().all(|t| *t == "flaky"));
+    }
+}
@@ -0,0 +1,9 @@
+//! the fatal error of a `RetryableResult` has to be the `FatalError` of its recoverable error
+//!     so that `to_fatal` inside the retry loops always gives the type the caller gets back
+//! these check that a mismatch is a compile error and not something that gets through
+
+#[test]
+fn mismatched_fatal_error_does_not_compile() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/fatal_error_agrees/*.rs");
+}
@@ -0,0 +1,28 @@
+use retryable_result::{repeatedly_try, Retryable, RetryableResult};
+use std::time::{Duration, Instant};
+
+struct Timeout;
+
+impl Retryable for Timeout {
+    type FatalError = u8;
+
+    fn to_fatal(self) -> Self::FatalError {
+        0
+    }
+
+    fn wait_time(&self, _: Instant, _: &[(Self, Instant)]) -> Option<Duration> {
+        None
+    }
+}
+
+fn main() {
+    let _: Result<(), u16> = async_std::task::block_on(repeatedly_try(
+        |()| async { RetryableResult::<(), Timeout, u8>::Retryable(Timeout) },
+        (),
+        (
+            &mut (),
+            None::<fn(&u16, Instant, &mut ())>,
+            None::<fn(&Timeout, Instant, &mut ())>,
+        ),
+    ));
+}
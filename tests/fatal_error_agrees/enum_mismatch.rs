@@ -0,0 +1,20 @@
+use retryable_result::{Retryable, RetryableResult};
+use std::time::{Duration, Instant};
+
+struct Timeout;
+
+impl Retryable for Timeout {
+    type FatalError = u8;
+
+    fn to_fatal(self) -> Self::FatalError {
+        0
+    }
+
+    fn wait_time(&self, _: Instant, _: &[(Self, Instant)]) -> Option<Duration> {
+        None
+    }
+}
+
+fn main() {
+    let _: RetryableResult<(), Timeout, u16> = RetryableResult::Fatal(1);
+}
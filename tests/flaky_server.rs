@@ -0,0 +1,139 @@
+//! the whole stack against a real socket
+//!     a tiny HTTP server answers 503 a set number of times before answering 200
+//!     and the client retries it with `repeatedly_try` and an `ExponentialBackoff`
+//! there is no HTTP client dependency, the client writes the request by hand
+
+use async_std::io::{ReadExt, WriteExt};
+use http::StatusCode;
+use retryable_result::{repeatedly_try, Backoff, ExponentialBackoff, Retryable, RetryableResult};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const POLICY: ExponentialBackoff = ExponentialBackoff {
+    base: Duration::from_millis(1),
+    multiplier: 2,
+    max_wait: Duration::from_millis(20),
+    max_attempts: 5,
+};
+
+struct ServiceUnavailable;
+
+impl Retryable for ServiceUnavailable {
+    type FatalError = StatusCode;
+
+    fn to_fatal(self) -> Self::FatalError {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        POLICY.next_delay(my_time, previous_retriable_failures)
+    }
+}
+
+/// answers 503 to the first `failures` requests and 200 to the rest, counting every request
+fn flaky_server(failures: usize) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("can bind to localhost");
+    let addr = listener
+        .local_addr()
+        .expect("bound listener has an address");
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&hits);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let status = if counted.fetch_add(1, Ordering::SeqCst) < failures {
+                "503 Service Unavailable"
+            } else {
+                "200 OK"
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok"
+            );
+        }
+    });
+    (addr, hits)
+}
+
+async fn get(addr: SocketAddr) -> RetryableResult<String, ServiceUnavailable, StatusCode> {
+    let Ok(mut stream) = async_std::net::TcpStream::connect(addr).await else {
+        return RetryableResult::Retryable(ServiceUnavailable);
+    };
+    let request = format!("GET / HTTP/1.1\r\nhost: {addr}\r\nconnection: close\r\n\r\n");
+    if stream.write_all(request.as_bytes()).await.is_err() {
+        return RetryableResult::Retryable(ServiceUnavailable);
+    }
+    let mut response = String::new();
+    if stream.read_to_string(&mut response).await.is_err() {
+        return RetryableResult::Retryable(ServiceUnavailable);
+    }
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok());
+    match status {
+        Some(StatusCode::OK) => {
+            RetryableResult::GoodResult(response.rsplit("\r\n\r\n").next().unwrap_or("").into())
+        }
+        Some(StatusCode::SERVICE_UNAVAILABLE) => RetryableResult::Retryable(ServiceUnavailable),
+        Some(other) => RetryableResult::Fatal(other),
+        None => RetryableResult::Fatal(StatusCode::BAD_GATEWAY),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn succeeds_after_503s() {
+    let (addr, hits) = flaky_server(3);
+    let z = repeatedly_try(
+        get,
+        addr,
+        (
+            &mut (),
+            None::<fn(&StatusCode, Instant, &mut ())>,
+            None::<fn(&ServiceUnavailable, Instant, &mut ())>,
+        ),
+    )
+    .await;
+    assert_eq!(z, Ok("ok".to_string()));
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn gives_up_when_503s_outlast_the_policy() {
+    let (addr, hits) = flaky_server(usize::MAX);
+    let mut logged = Vec::new();
+    let z = repeatedly_try(
+        get,
+        addr,
+        (
+            &mut logged,
+            Some(|f: &StatusCode, _: Instant, logged: &mut Vec<String>| {
+                logged.push(format!("fatal {}", f.as_u16()));
+            }),
+            Some(
+                |_: &ServiceUnavailable, _: Instant, logged: &mut Vec<String>| {
+                    logged.push("recoverable".to_string());
+                },
+            ),
+        ),
+    )
+    .await;
+    assert_eq!(z, Err(StatusCode::SERVICE_UNAVAILABLE));
+    assert_eq!(hits.load(Ordering::SeqCst), POLICY.max_attempts);
+    assert_eq!(logged.len(), POLICY.max_attempts);
+    assert_eq!(logged.last().map(String::as_str), Some("fatal 503"));
+}
@@ -0,0 +1,8 @@
+//! `repeatedly_try_idempotent` only takes an argument whose type was marked `Idempotent`
+//! these check that forgetting the marker is a compile error
+
+#[test]
+fn unmarked_argument_does_not_compile() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/idempotent_only/*.rs");
+}
@@ -0,0 +1,25 @@
+use retryable_result::{repeatedly_try_idempotent, Retryable, RetryableResult};
+use std::time::{Duration, Instant};
+
+#[derive(Clone)]
+struct ChargeCard;
+
+struct Timeout;
+
+impl Retryable for Timeout {
+    type FatalError = u8;
+
+    fn to_fatal(self) -> Self::FatalError {
+        0
+    }
+
+    fn wait_time(&self, _: Instant, _: &[(Self, Instant)]) -> Option<Duration> {
+        None
+    }
+}
+
+fn main() {
+    let one_try = |_: ChargeCard| async { RetryableResult::<(), Timeout, u8>::Retryable(Timeout) };
+    let loggers = (&mut (), None::<fn(&u8, Instant, &mut ())>, None::<fn(&Timeout, Instant, &mut ())>);
+    let _ = repeatedly_try_idempotent(one_try, ChargeCard, loggers);
+}
@@ -0,0 +1,120 @@
+//! the retry loops have to be usable from background tasks on a multi threaded runtime
+//!     so these only compile if their futures are `Send` and `'static` for `Send` inputs
+
+use retryable_result::{
+    repeatedly_try, retry_if, Backoff, ExponentialBackoff, RetryBuilder, Retryable, RetryableResult,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const POLICY: ExponentialBackoff = ExponentialBackoff {
+    base: Duration::from_millis(1),
+    multiplier: 2,
+    max_wait: Duration::from_millis(5),
+    max_attempts: 5,
+};
+
+#[derive(Debug)]
+struct Busy;
+
+impl Retryable for Busy {
+    type FatalError = String;
+
+    fn to_fatal(self) -> Self::FatalError {
+        "busy".to_string()
+    }
+
+    fn wait_time(
+        &self,
+        my_time: Instant,
+        previous_retriable_failures: &[(Self, Instant)],
+    ) -> Option<Duration> {
+        POLICY.next_delay(my_time, previous_retriable_failures)
+    }
+}
+
+async fn busy_twice(attempts: Arc<AtomicUsize>, x: u8) -> RetryableResult<u8, Busy, String> {
+    tokio::task::yield_now().await;
+    if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+        RetryableResult::Retryable(Busy)
+    } else {
+        RetryableResult::GoodResult(x)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn repeatedly_try_can_be_spawned() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&attempts);
+    let one_try = move |x| busy_twice(Arc::clone(&counted), x);
+    let handle = tokio::spawn(async move {
+        let mut logged = Vec::new();
+        repeatedly_try(
+            one_try,
+            7,
+            (
+                &mut logged,
+                Some(|f: &String, _: Instant, logged: &mut Vec<String>| logged.push(f.clone())),
+                Some(|r: &Busy, _: Instant, logged: &mut Vec<String>| {
+                    logged.push(format!("{r:?}"));
+                }),
+            ),
+        )
+        .await
+    });
+    assert_eq!(handle.await.expect("task did not panic"), Ok(7));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn builder_with_hooks_can_be_spawned() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&attempts);
+    let one_try = move |x| busy_twice(Arc::clone(&counted), x);
+    let builder = RetryBuilder::new()
+        .health_check(|| true)
+        .max_wait(Duration::from_millis(2))
+        .total_timeout(Duration::from_secs(5));
+    let handle = tokio::spawn(async move {
+        builder
+            .run(
+                one_try,
+                3,
+                (
+                    &mut (),
+                    None::<fn(&String, Instant, &mut ())>,
+                    None::<fn(&Busy, Instant, &mut ())>,
+                ),
+            )
+            .await
+    });
+    assert_eq!(handle.await.expect("task did not panic"), Ok(3));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn retry_if_can_be_spawned() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&attempts);
+    let handle = tokio::spawn(retry_if(
+        move |()| {
+            let counted = Arc::clone(&counted);
+            async move {
+                if counted.fetch_add(1, Ordering::SeqCst) < 1 {
+                    Err("timeout")
+                } else {
+                    Ok("done")
+                }
+            }
+        },
+        (),
+        |e: &&str| *e == "timeout",
+        POLICY,
+    ));
+    assert_eq!(handle.await.expect("task did not panic"), Ok("done"));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}